@@ -4,6 +4,8 @@ use serde_json::Value;
 use std::collections::HashMap;
 use log::trace;
 
+use crate::encode::{encode_query_value, encode_segment};
+
 /// The conduit API URL
 pub const API_URL: &'static str = "https://conduit.productionready.io/api";
 
@@ -12,13 +14,36 @@ pub mod request {
     use mogwai::prelude::{utils, JsFuture};
     use serde::{de::DeserializeOwned, Deserialize, Serialize};
     use snafu::{OptionExt, ResultExt, Snafu};
-    use std::collections::HashMap;
+    use std::{cell::RefCell, collections::HashMap};
     use wasm_bindgen::{JsCast, JsValue};
     use web_sys::{Headers, Request, RequestInit, RequestMode, Response};
 
+    use crate::cache::Lru;
+
+    /// How many prefetched responses are kept.
+    const PREFETCHED_SIZE: usize = 32;
+
+    /// How long a prefetched response is good for, in milliseconds.
+    const PREFETCHED_TTL: f64 = 30_000.0;
+
+    /// A GET request's URL and auth token.
+    type RequestKey = (String, Option<String>);
+
+    thread_local! {
+        /// Responses fetched ahead of time, with when they were fetched. Each is used by
+        /// at most one request.
+        static PREFETCHED: RefCell<Lru<RequestKey, (f64, JsValue)>> =
+            RefCell::new(Lru::new(PREFETCHED_SIZE));
+    }
+
+    /// {
+    ///     "errors": {
+    ///         "body": ["can't be empty"]
+    ///     }
+    /// }
     #[derive(Debug, Deserialize)]
     pub struct ApiResponseErrors {
-        errors: HashMap<String, Vec<String>>,
+        pub errors: HashMap<String, Vec<String>>,
     }
 
     /// An enumeration of all API errors.
@@ -51,24 +76,62 @@ pub mod request {
         },
     }
 
-    impl From<Error> for Vec<String> {
-        fn from(err: Error) -> Vec<String> {
+    /// An error's messages, kept by the request field each is about so forms can show
+    /// them next to the right input.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct FieldErrors {
+        /// Messages about a field, eg. `("email", ["is invalid"])`.
+        pub fields: Vec<(String, Vec<String>)>,
+        /// Messages that aren't about any one field.
+        pub others: Vec<String>,
+    }
+
+    impl FieldErrors {
+        pub fn other(message: String) -> Self {
+            FieldErrors {
+                fields: vec![],
+                others: vec![message],
+            }
+        }
+
+        /// The messages about the given field.
+        pub fn get(&self, field: &str) -> Vec<String> {
+            self.fields
+                .iter()
+                .find(|(name, _)| name == field)
+                .map(|(_, messages)| messages.clone())
+                .unwrap_or_default()
+        }
+
+        /// The messages a form showing the given fields has to list apart from them. These
+        /// are the ones about other fields, eg. "email or password", and about no field.
+        pub fn unshown(&self, shown: &[&str]) -> Vec<String> {
+            self.fields
+                .iter()
+                .filter(|(name, _)| !shown.contains(&name.as_str()))
+                .flat_map(|(name, messages)| {
+                    messages
+                        .iter()
+                        .map(move |message| format!("{} {}", name, message))
+                })
+                .chain(self.others.iter().cloned())
+                .collect()
+        }
+    }
+
+    impl From<Error> for FieldErrors {
+        fn from(err: Error) -> FieldErrors {
             match err {
-                Error::ResponseErrors { errors } => errors
-                    .into_iter()
-                    .flat_map(|(name, descs)| -> Vec<String> {
-                        descs
-                            .into_iter()
-                            .map(|desc| format!("{} {}", name, desc))
-                            .collect()
-                    })
-                    .collect(),
-                _ => vec![format!("{}", err)],
+                Error::ResponseErrors { errors } => FieldErrors {
+                    fields: errors,
+                    others: vec![],
+                },
+                _ => FieldErrors::other(format!("{}", err)),
             }
         }
     }
 
-    async fn send_request<T: DeserializeOwned>(req: Request) -> Result<T, Error> {
+    async fn fetch_json(req: Request) -> Result<JsValue, Error> {
         let resp: Response = JsFuture::from(utils::window().fetch_with_request(&req))
             .await
             .ok()
@@ -76,10 +139,13 @@ pub mod request {
             .dyn_into()
             .ok()
             .with_context(|| MalformedResponse)?;
-        let js_value: JsValue = JsFuture::from(resp.json().ok().with_context(|| FailedJson)?)
+        JsFuture::from(resp.json().ok().with_context(|| FailedJson)?)
             .await
             .ok()
-            .with_context(|| FailedAwaitingJson)?;
+            .with_context(|| FailedAwaitingJson)
+    }
+
+    fn decode_json<T: DeserializeOwned>(js_value: &JsValue) -> Result<T, Error> {
         js_value
             .into_serde::<T>()
             .with_context(|| DeserializeFailure)
@@ -122,6 +188,13 @@ pub mod request {
         o_body: Option<&T>,
         o_auth: Option<&str>,
     ) -> Result<S, Error> {
+        let key = (url.to_string(), o_auth.map(String::from));
+        if method == "GET" {
+            if let Some(js_value) = take_prefetched(&key) {
+                return decode_json(&js_value);
+            }
+        }
+
         let mut opts = req_init(o_body, o_auth)?;
         opts.method(method);
         opts.mode(RequestMode::Cors);
@@ -130,7 +203,42 @@ pub mod request {
             .ok()
             .with_context(|| ConstructRequest)?;
 
-        send_request::<S>(req).await
+        decode_json(&fetch_json(req).await?)
+    }
+
+    /// Send a GET request ahead of time. If it succeeds, the next identical request made
+    /// within `PREFETCHED_TTL` uses its response instead of going to the server.
+    pub async fn prefetch<S: DeserializeOwned>(
+        url: &str,
+        o_auth: Option<&str>,
+    ) -> Result<(), Error> {
+        let mut opts = req_init::<()>(None, o_auth)?;
+        opts.method("GET");
+
+        let req = Request::new_with_str_and_init(url, &opts)
+            .ok()
+            .with_context(|| ConstructRequest)?;
+
+        let js_value = fetch_json(req).await?;
+        // errors aren't worth keeping
+        decode_json::<S>(&js_value)?;
+        let key = (url.to_string(), o_auth.map(String::from));
+        PREFETCHED.with(|prefetched| {
+            prefetched
+                .borrow_mut()
+                .insert(key, (js_sys::Date::now(), js_value))
+        });
+        Ok(())
+    }
+
+    fn take_prefetched(key: &RequestKey) -> Option<JsValue> {
+        let (fetched_at, js_value) =
+            PREFETCHED.with(|prefetched| prefetched.borrow_mut().remove(key))?;
+        if js_sys::Date::now() - fetched_at < PREFETCHED_TTL {
+            Some(js_value)
+        } else {
+            None
+        }
     }
 }
 
@@ -160,8 +268,11 @@ struct UserWrapper<T> {
 /// A user's registration or login.
 #[derive(Clone, Debug, Serialize)]
 pub struct UserRegistration {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub password: Option<String>,
 }
 
@@ -181,12 +292,18 @@ pub async fn register_user(user: UserRegistration) -> Result<User, request::Erro
     Ok(user)
 }
 
+/// A user's settings update. Only the fields that are `Some` are sent.
 #[derive(Clone, Debug, Serialize)]
 pub struct UserUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub bio: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub image: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub password: Option<String>,
 }
 
@@ -218,7 +335,7 @@ pub async fn update_user(user: UserUpdate, token: &str) -> Result<User, request:
 ///         "following": false
 ///     }
 /// }
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct UserProfile {
     pub username: String,
     pub bio: Option<String>,
@@ -247,11 +364,20 @@ pub async fn get_profile(
     username: &str,
     o_token: Option<&str>,
 ) -> Result<UserProfile, request::Error> {
-    let url = format!("{}/profiles/{}", API_URL, username);
-    let ProfileWrapper { profile } = request::api::<(), _>(&url, "GET", None, o_token).await?;
+    let ProfileWrapper { profile } =
+        request::api::<(), _>(&profile_url(username), "GET", None, o_token).await?;
     Ok(profile)
 }
 
+/// Warm the cache for `get_profile`.
+pub async fn prefetch_profile(username: &str, o_token: Option<&str>) -> Result<(), request::Error> {
+    request::prefetch::<ProfileWrapper<UserProfile>>(&profile_url(username), o_token).await
+}
+
+fn profile_url(username: &str) -> String {
+    format!("{}/profiles/{}", API_URL, encode_segment(username))
+}
+
 /// {
 ///     "article": {
 ///         "slug": "how-to-train-your-dragon",
@@ -271,7 +397,7 @@ pub async fn get_profile(
 ///         }
 ///     }
 /// }
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Article {
     pub slug: String,
@@ -286,7 +412,7 @@ pub struct Article {
     pub author: UserProfile,
 }
 
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Articles {
     pub articles: Vec<Article>,
@@ -302,10 +428,34 @@ pub async fn get_articles(
     o_offset: Option<u32>,
     o_token: Option<&str>,
 ) -> Result<Articles, request::Error> {
+    let url = articles_url(o_author, o_tag, o_favorited, o_limit, o_offset);
+    request::api::<(), Articles>(&url, "GET", None, o_token).await
+}
+
+/// Warm the cache for `get_articles`.
+pub async fn prefetch_articles(
+    o_author: Option<&str>,
+    o_tag: Option<&str>,
+    o_favorited: Option<&str>,
+    o_limit: Option<u32>,
+    o_offset: Option<u32>,
+    o_token: Option<&str>,
+) -> Result<(), request::Error> {
+    let url = articles_url(o_author, o_tag, o_favorited, o_limit, o_offset);
+    request::prefetch::<Articles>(&url, o_token).await
+}
+
+fn articles_url(
+    o_author: Option<&str>,
+    o_tag: Option<&str>,
+    o_favorited: Option<&str>,
+    o_limit: Option<u32>,
+    o_offset: Option<u32>,
+) -> String {
     let params: Vec<String> = vec![
-        o_tag.map(|t| format!("tag={}", t)),
-        o_author.map(|a| format!("author={}", a)),
-        o_favorited.map(|f| format!("favorited={}", f)),
+        o_tag.map(|t| format!("tag={}", encode_query_value(t))),
+        o_author.map(|a| format!("author={}", encode_query_value(a))),
+        o_favorited.map(|f| format!("favorited={}", encode_query_value(f))),
         o_limit.map(|l| format!("limit={}", l)),
         o_offset.map(|o| format!("offset={}", o)),
     ]
@@ -317,6 +467,277 @@ pub async fn get_articles(
     } else {
         format!("?{}", params.join("&"))
     };
-    let url = format!("{}/articles{}", API_URL, params);
-    request::api::<(), Articles>(&url, "GET", None, o_token).await
+    format!("{}/articles{}", API_URL, params)
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+struct ArticleWrapper<T> {
+    article: T,
+}
+
+/// GET /api/articles/:slug
+pub async fn get_article(slug: &str, o_token: Option<&str>) -> Result<Article, request::Error> {
+    let ArticleWrapper { article } =
+        request::api::<(), _>(&article_url(slug), "GET", None, o_token).await?;
+    Ok(article)
+}
+
+/// Warm the cache for `get_article`.
+pub async fn prefetch_article(slug: &str, o_token: Option<&str>) -> Result<(), request::Error> {
+    request::prefetch::<ArticleWrapper<Article>>(&article_url(slug), o_token).await
+}
+
+fn article_url(slug: &str) -> String {
+    format!("{}/articles/{}", API_URL, encode_segment(slug))
+}
+
+/// {
+///     "tags": [
+///         "reactjs",
+///         "angularjs"
+///     ]
+/// }
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Tags {
+    pub tags: Vec<String>,
+}
+
+/// GET /api/tags
+pub async fn get_tags() -> Result<Vec<String>, request::Error> {
+    let url = format!("{}/tags", API_URL);
+    let Tags { tags } = request::api::<(), _>(&url, "GET", None, None).await?;
+    Ok(tags)
+}
+
+#[cfg(test)]
+mod api_tests {
+    //! Contract tests that pin our models to the RealWorld API spec's example
+    //! payloads. If the backend's JSON drifts these should fail.
+    use super::*;
+    use serde::de::DeserializeOwned;
+    use serde_json::json;
+
+    fn decode<T: DeserializeOwned>(value: &Value) -> T {
+        serde_json::from_value(value.clone())
+            .unwrap_or_else(|e| panic!("could not decode {}: {}", value, e))
+    }
+
+    fn encode<T: Serialize>(item: &T) -> Value {
+        serde_json::to_value(item).unwrap()
+    }
+
+    fn jake_profile_json() -> Value {
+        json!({
+            "username": "jake",
+            "bio": "I work at statefarm",
+            "image": "https://static.productionready.io/images/smiley-cyrus.jpg",
+            "following": false
+        })
+    }
+
+    fn dragon_article_json() -> Value {
+        json!({
+            "slug": "how-to-train-your-dragon",
+            "title": "How to train your dragon",
+            "description": "Ever wonder how?",
+            "body": "It takes a Jacobian",
+            "tagList": ["dragons", "training"],
+            "createdAt": "2016-02-18T03:22:56.637Z",
+            "updatedAt": "2016-02-18T03:48:35.824Z",
+            "favorited": false,
+            "favoritesCount": 0,
+            "author": jake_profile_json()
+        })
+    }
+
+    #[test]
+    fn can_decode_user_response() {
+        let fixture = json!({
+            "user": {
+                "email": "jake@jake.jake",
+                "token": "jwt.token.here",
+                "username": "jake",
+                "bio": "I work at statefarm",
+                "image": null
+            }
+        });
+        let UserWrapper { user } = decode::<UserWrapper<User>>(&fixture);
+        assert_eq!(
+            user,
+            User {
+                email: "jake@jake.jake".into(),
+                token: "jwt.token.here".into(),
+                username: "jake".into(),
+                bio: Some("I work at statefarm".into()),
+                image: None,
+            }
+        );
+        assert_eq!(encode(&UserWrapper { user }), fixture);
+    }
+
+    #[test]
+    fn can_encode_login_request() {
+        let login = UserRegistration {
+            email: Some("jake@jake.jake".into()),
+            username: None,
+            password: Some("jakejake".into()),
+        };
+        assert_eq!(
+            encode(&UserWrapper { user: login }),
+            json!({
+                "user": {
+                    "email": "jake@jake.jake",
+                    "password": "jakejake"
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn can_encode_registration_request() {
+        let registration = UserRegistration {
+            email: Some("jake@jake.jake".into()),
+            username: Some("Jacob".into()),
+            password: Some("jakejake".into()),
+        };
+        assert_eq!(
+            encode(&UserWrapper { user: registration }),
+            json!({
+                "user": {
+                    "username": "Jacob",
+                    "email": "jake@jake.jake",
+                    "password": "jakejake"
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn can_encode_update_request() {
+        let update = UserUpdate {
+            email: Some("jake@jake.jake".into()),
+            bio: Some("I like to skateboard".into()),
+            image: Some("https://i.stack.imgur.com/xHWG8.jpg".into()),
+            ..UserUpdate::default()
+        };
+        assert_eq!(
+            encode(&UserWrapper { user: update }),
+            json!({
+                "user": {
+                    "email": "jake@jake.jake",
+                    "bio": "I like to skateboard",
+                    "image": "https://i.stack.imgur.com/xHWG8.jpg"
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn can_decode_profile_response() {
+        let fixture = json!({ "profile": jake_profile_json() });
+        let ProfileWrapper { profile } = decode::<ProfileWrapper<UserProfile>>(&fixture);
+        assert_eq!(profile.username, "jake");
+        assert_eq!(profile.bio.as_deref(), Some("I work at statefarm"));
+        assert!(!profile.following);
+        assert_eq!(encode(&ProfileWrapper { profile }), fixture);
+    }
+
+    #[test]
+    fn can_decode_single_article_response() {
+        let fixture = json!({ "article": dragon_article_json() });
+        let article = decode::<Article>(&fixture["article"]);
+        assert_eq!(article.slug, "how-to-train-your-dragon");
+        assert_eq!(article.tag_list, vec!["dragons", "training"]);
+        assert_eq!(article.favorites_count, 0);
+        assert_eq!(
+            article.created_at,
+            "2016-02-18T03:22:56.637Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert_eq!(article.author.username, "jake");
+        assert_eq!(encode(&article), fixture["article"]);
+    }
+
+    #[test]
+    fn can_decode_multiple_articles_response() {
+        let fixture = json!({
+            "articles": [dragon_article_json(), dragon_article_json()],
+            "articlesCount": 2
+        });
+        let articles = decode::<Articles>(&fixture);
+        assert_eq!(articles.articles.len(), 2);
+        assert_eq!(articles.articles_count, 2);
+        assert_eq!(encode(&articles), fixture);
+    }
+
+    #[test]
+    fn can_decode_tags_response() {
+        let fixture = json!({ "tags": ["reactjs", "angularjs"] });
+        let tags = decode::<Tags>(&fixture);
+        assert_eq!(tags.tags, vec!["reactjs", "angularjs"]);
+        assert_eq!(encode(&tags), fixture);
+    }
+
+    #[test]
+    fn can_decode_error_response() {
+        let fixture = json!({
+            "errors": {
+                "body": ["can't be empty"]
+            }
+        });
+        let request::ApiResponseErrors { errors } = decode(&fixture);
+        assert_eq!(
+            errors.get("body"),
+            Some(&vec!["can't be empty".to_string()])
+        );
+    }
+
+    #[test]
+    fn keeps_errors_by_field() {
+        let errors = request::FieldErrors::from(request::Error::ResponseErrors {
+            errors: vec![
+                ("email".to_string(), vec!["is invalid".to_string()]),
+                (
+                    "email or password".to_string(),
+                    vec!["is invalid".to_string()],
+                ),
+            ],
+        });
+        assert_eq!(errors.get("email"), vec!["is invalid".to_string()]);
+        assert!(errors.get("password").is_empty());
+        assert_eq!(
+            errors.unshown(&["email", "password"]),
+            vec!["email or password is invalid".to_string()]
+        );
+
+        let errors = request::FieldErrors::from(request::Error::RequestFailure);
+        assert_eq!(
+            errors.unshown(&["email"]),
+            vec!["request failure".to_string()]
+        );
+    }
+
+    #[test]
+    fn rejects_snake_case_article_fields() {
+        let mut article = dragon_article_json();
+        let tags = article["tagList"].take();
+        let object = article.as_object_mut().unwrap();
+        object.remove("tagList");
+        object.insert("tag_list".into(), tags);
+        assert!(serde_json::from_value::<Article>(article).is_err());
+    }
+
+    #[test]
+    fn can_build_articles_urls() {
+        assert_eq!(
+            articles_url(None, None, None, None, None),
+            format!("{}/articles", API_URL)
+        );
+        assert_eq!(
+            articles_url(Some("jake smith"), Some("c++"), None, Some(10), Some(20)),
+            format!(
+                "{}/articles?tag=c%2B%2B&author=jake%20smith&limit=10&offset=20",
+                API_URL
+            )
+        );
+    }
 }
@@ -0,0 +1,89 @@
+//! A small least-recently-used cache.
+use std::collections::VecDeque;
+
+/// Keeps up to `capacity` values, forgetting the least recently used first.
+///
+/// Lookups are linear, which is fine for the handful of entries we keep.
+pub struct Lru<K, V> {
+    capacity: usize,
+    /// Most recently used first.
+    entries: VecDeque<(K, V)>,
+}
+
+impl<K: PartialEq, V> Lru<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Lru {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// The value kept for the given key, if any, marking it as recently used.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let index = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(index)?;
+        self.entries.push_front(entry);
+        self.entries.front().map(|(_, v)| v)
+    }
+
+    /// Whether a value is kept for the given key. Doesn't count as a use.
+    pub fn contains(&self, key: &K) -> bool {
+        self.entries.iter().any(|(k, _)| k == key)
+    }
+
+    /// Keep a value, replacing any kept for the same key. Returns the entry that had to
+    /// be forgotten to make room, if any.
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        if let Some(index) = self.entries.iter().position(|(k, _)| k == &key) {
+            self.entries.remove(index);
+        }
+        self.entries.push_front((key, value));
+        if self.entries.len() > self.capacity {
+            self.entries.pop_back()
+        } else {
+            None
+        }
+    }
+
+    /// Forget the value kept for the given key, returning it.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let index = self.entries.iter().position(|(k, _)| k == key)?;
+        self.entries.remove(index).map(|(_, v)| v)
+    }
+
+    /// Forget everything.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use super::*;
+
+    #[test]
+    fn forgets_least_recently_used_first() {
+        let mut lru = Lru::new(2);
+        assert_eq!(lru.insert("feed", 1), None);
+        assert_eq!(lru.insert("article", 2), None);
+        assert_eq!(lru.get(&"feed"), Some(&1));
+        assert_eq!(lru.insert("profile", 3), Some(("article", 2)));
+        assert!(lru.contains(&"feed"));
+        assert!(!lru.contains(&"article"));
+        assert!(lru.contains(&"profile"));
+    }
+
+    #[test]
+    fn inserting_again_replaces() {
+        let mut lru = Lru::new(2);
+        lru.insert("feed", 1);
+        lru.insert("article", 2);
+        assert_eq!(lru.insert("feed", 3), None);
+        assert!(lru.contains(&"article"));
+        assert_eq!(lru.get(&"feed"), Some(&3));
+        assert_eq!(lru.remove(&"article"), Some(2));
+        assert_eq!(lru.remove(&"article"), None);
+        lru.clear();
+        assert_eq!(lru.get(&"feed"), None);
+    }
+}
@@ -1,6 +1,8 @@
 //! All of the application's sub-components.
+pub mod auth_form;
 pub mod login;
 pub mod nav;
 pub mod profile;
 pub mod register;
 pub mod settings;
+pub mod toast;
@@ -0,0 +1,266 @@
+//! The form shared by signing in and signing up.
+//!
+//! It checks its inputs, submits on Enter, and shows what went wrong, but leaves sending
+//! the request to its owner: submitting sends `Out::Submit` to the owner, which answers
+//! with `In::Failure` if the request fails.
+#![allow(unused_braces)]
+use mogwai::prelude::*;
+use web_sys::HtmlInputElement;
+
+use crate::{
+    api::{request::FieldErrors, UserRegistration},
+    widgets::{
+        field_builder, FormValidity, SubmitButton, SubmitButtonMsg, TextInput, TextInputIn,
+        TextInputOut, Validator,
+    },
+};
+
+/// The fields an auth form can show.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AuthField {
+    Username,
+    Email,
+    /// An existing password.
+    Password,
+    /// A password being chosen, held to a minimum length.
+    NewPassword,
+    /// Whether to stay signed in after closing the browser.
+    RememberMe,
+}
+
+impl AuthField {
+    /// The field's text input, if it has one.
+    fn text_input(&self) -> Option<TextInput<HtmlInputElement>> {
+        let input = match self {
+            AuthField::Username => TextInput::new("", "Your Name").with_validators(
+                "username",
+                vec![Validator::Required, Validator::MaxLength(32)],
+            ),
+            AuthField::Email => TextInput::new("", "Email")
+                .with_validators("email", vec![Validator::Required, Validator::Email]),
+            AuthField::Password => TextInput::new("", "Password")
+                .with_type("password")
+                .with_validators("password", vec![Validator::Required]),
+            AuthField::NewPassword => TextInput::new("", "Password")
+                .with_type("password")
+                .with_validators(
+                    "password",
+                    vec![Validator::Required, Validator::MinLength(8)],
+                ),
+            AuthField::RememberMe => return None,
+        };
+        Some(input)
+    }
+}
+
+pub struct AuthForm {
+    inputs: Vec<(AuthField, Gizmo<TextInput<HtmlInputElement>>)>,
+    shows_remember_me: bool,
+    o_remember_input: Option<HtmlInputElement>,
+    validity: FormValidity,
+    submit_button: SubmitButton,
+}
+
+impl AuthForm {
+    /// The submitting label is shown on the button while waiting for the response, eg.
+    /// "Signing in…".
+    pub fn new(fields: &[AuthField], submit_label: &str, submitting_label: &str) -> Self {
+        let mut validity = FormValidity::default();
+        let mut inputs = vec![];
+        for field in fields {
+            if let Some(input) = field.text_input() {
+                validity.check(&input);
+                inputs.push((*field, Gizmo::from(input)));
+            }
+        }
+        AuthForm {
+            inputs,
+            shows_remember_me: fields.contains(&AuthField::RememberMe),
+            o_remember_input: None,
+            validity,
+            submit_button: SubmitButton::new(submit_label, submitting_label),
+        }
+    }
+
+    fn value(&self, field: AuthField) -> Option<String> {
+        let (_, input) = self.inputs.iter().find(|(f, _)| *f == field)?;
+        match input.state.borrow().value.as_str() {
+            "" => None,
+            s => Some(s.into()),
+        }
+    }
+
+    fn registration(&self) -> UserRegistration {
+        UserRegistration {
+            username: self.value(AuthField::Username),
+            email: self.value(AuthField::Email),
+            password: self
+                .value(AuthField::Password)
+                .or_else(|| self.value(AuthField::NewPassword)),
+        }
+    }
+
+    fn remember_builder(&self, tx: &Transmitter<In>) -> ViewBuilder<HtmlElement> {
+        builder! {
+            <fieldset class="form-group">
+                <label class="form-check-label">
+                    <input
+                        cast:type = HtmlInputElement
+                        class="form-check-input"
+                        type="checkbox"
+                        checked="checked"
+                        post:build=tx.contra_map(|el: &HtmlInputElement| {
+                            In::RememberInput(el.clone())
+                        })
+                        />
+                    " Remember me"
+                </label>
+            </fieldset>
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum In {
+    RememberInput(HtmlInputElement),
+    Validity {
+        name: String,
+        is_valid: bool,
+    },
+    Submit,
+    /// The owner's request failed.
+    Failure(FieldErrors),
+}
+
+#[derive(Clone)]
+pub enum Out {
+    /// The user submitted the form, for the owner to send.
+    Submit {
+        registration: UserRegistration,
+        remember: bool,
+    },
+    Error(Patch<View<HtmlElement>>),
+    Button(SubmitButtonMsg),
+}
+
+impl Out {
+    /// What was submitted, if this is a submission.
+    pub fn submitted(&self) -> Option<(UserRegistration, bool)> {
+        match self {
+            Out::Submit {
+                registration,
+                remember,
+            } => Some((registration.clone(), *remember)),
+            _ => None,
+        }
+    }
+
+    fn errors(&self) -> Option<Patch<View<HtmlElement>>> {
+        match self {
+            Out::Error(patch) => Some(patch.clone()),
+            _ => None,
+        }
+    }
+
+    fn button(&self) -> Option<SubmitButtonMsg> {
+        match self {
+            Out::Button(msg) => Some(msg.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl Component for AuthForm {
+    type ModelMsg = In;
+    type ViewMsg = Out;
+    type DomNode = HtmlElement;
+
+    fn bind(&self, sub: &Subscriber<In>) {
+        for (_, input) in self.inputs.iter() {
+            sub.subscribe_filter_map(&input.recv, |msg: &TextInputOut| {
+                msg.validity()
+                    .map(|(name, is_valid)| In::Validity { name, is_valid })
+            });
+        }
+    }
+
+    fn update(&mut self, msg: &In, tx: &Transmitter<Out>, _sub: &Subscriber<In>) {
+        match msg {
+            In::RememberInput(input) => {
+                self.o_remember_input = Some(input.clone());
+            }
+            In::Validity { name, is_valid } => {
+                self.validity.set(name, *is_valid);
+                tx.send(&Out::Button(SubmitButtonMsg::CanSubmit(
+                    self.validity.is_valid(),
+                )));
+            }
+            In::Submit => {
+                if !self.submit_button.can_submit(&self.validity) {
+                    return;
+                }
+                tx.send(&Out::Button(self.submit_button.set_submitting(true)));
+                // Decide where to keep the user now, in case the box is toggled while
+                // the request is in flight.
+                let remember = self
+                    .o_remember_input
+                    .as_ref()
+                    .map(|input| input.checked())
+                    .unwrap_or(true);
+                tx.send(&Out::Submit {
+                    registration: self.registration(),
+                    remember,
+                });
+            }
+            In::Failure(errors) => {
+                tx.send(&Out::Button(self.submit_button.set_submitting(false)));
+                let mut shown = vec![];
+                for (_, input) in self.inputs.iter() {
+                    let name = input.state.borrow().name.clone();
+                    input.send(&TextInputIn::ServerErrors(errors.get(&name)));
+                    shown.push(name);
+                }
+                let shown = shown.iter().map(String::as_str).collect::<Vec<_>>();
+                tx.send(&Out::Error(Patch::RemoveAll));
+                for error in errors.unshown(&shown).iter() {
+                    tx.send(&Out::Error(Patch::PushBack {
+                        value: view! {
+                            <li>{error}</li>
+                        },
+                    }));
+                }
+            }
+        }
+    }
+
+    fn view(&self, tx: &Transmitter<In>, rx: &Receiver<Out>) -> ViewBuilder<HtmlElement> {
+        let fields = self
+            .inputs
+            .iter()
+            .map(|(_, input)| field_builder(input))
+            .collect::<Vec<_>>();
+        let o_remember_me = if self.shows_remember_me {
+            Some(self.remember_builder(tx))
+        } else {
+            None
+        };
+        let button = rx.branch_filter_map(|msg| msg.button());
+        let is_submitting = button.branch_filter_map(|msg| msg.is_submitting());
+        // While submitting, the fieldset disables all of its inputs and the button.
+        builder! {
+            <form on:submit=tx.contra_map(|ev: &Event| {
+                ev.prevent_default();
+                In::Submit
+            })>
+                <ul class="error-messages"
+                    patch:children=rx.branch_filter_map(|msg| msg.errors())>
+                </ul>
+                <fieldset boolean:disabled=(false, is_submitting)>
+                    {fields}
+                    {o_remember_me}
+                    {self.submit_button.builder(self.validity.is_valid(), button)}
+                </fieldset>
+            </form>
+        }
+    }
+}
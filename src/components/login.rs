@@ -1,123 +1,92 @@
 //! The login component.
 #![allow(unused_braces)]
 use mogwai::prelude::*;
-use web_sys::{HtmlInputElement, Location};
 
 use crate::{
-    api::{self, User, UserRegistration},
-    route::Route,
-    store,
+    api::{self, request::FieldErrors, User, UserRegistration},
+    components::{
+        auth_form::{self, AuthField, AuthForm},
+        toast,
+    },
+    session::Session,
 };
 
-/// The registration UI component.
+/// The login UI component.
 pub struct Login {
-    email_input: Option<HtmlInputElement>,
-    password_input: Option<HtmlInputElement>,
-}
-
-impl Default for Login {
-    fn default() -> Self {
-        Login {
-            password_input: None,
-            email_input: None,
-        }
-    }
+    form: Gizmo<AuthForm>,
+    remember: bool,
+    session: Session,
 }
 
 impl Login {
-    // Here we just re-use UserRegistration
-    fn get_login(&self) -> UserRegistration {
-        let get_value = |o_input: &Option<HtmlInputElement>| -> Option<String> {
-            o_input
-                .as_ref()
-                .map(|input| match input.value().as_str() {
-                    "" => None,
-                    s => Some(s.into()),
-                })
-                .flatten()
-        };
-        let username = None;
-        let email = get_value(&self.email_input);
-        let password = get_value(&self.password_input);
-        UserRegistration {
-            username,
-            email,
-            password
+    pub fn new(session: Session) -> Self {
+        let fields = [AuthField::Email, AuthField::Password, AuthField::RememberMe];
+        Login {
+            form: Gizmo::from(AuthForm::new(&fields, "Sign in", "Signing in…")),
+            remember: true,
+            session,
         }
     }
 }
 
 #[derive(Clone, Debug)]
 pub enum In {
-    EmailInput(HtmlInputElement),
-    PasswordInput(HtmlInputElement),
-    Submit,
-    LoginSuccess { user: User },
-    LoginFailure { errors: Vec<String> },
-}
-
-#[derive(Clone)]
-pub enum Out {
-    Error(Patch<View<HtmlElement>>),
-}
-
-impl Out {
-    fn errors(&self) -> Option<Patch<View<HtmlElement>>> {
-        match self {
-            Out::Error(patch) => Some(patch.clone()),
-        }
-    }
+    Submit {
+        login: UserRegistration,
+        remember: bool,
+    },
+    LoginSuccess {
+        user: User,
+    },
+    LoginFailure {
+        errors: FieldErrors,
+    },
 }
 
 impl Component for Login {
     type ModelMsg = In;
-    type ViewMsg = Out;
+    type ViewMsg = ();
     type DomNode = HtmlElement;
 
-    fn update(&mut self, msg: &In, tx: &Transmitter<Out>, sub: &Subscriber<In>) {
+    fn bind(&self, sub: &Subscriber<In>) {
+        sub.subscribe_filter_map(&self.form.recv, |msg| {
+            msg.submitted()
+                .map(|(login, remember)| In::Submit { login, remember })
+        });
+    }
+
+    fn update(&mut self, msg: &In, _tx: &Transmitter<()>, sub: &Subscriber<In>) {
         match msg {
-            In::EmailInput(input) => {
-                self.email_input = Some(input.clone());
-            }
-            In::PasswordInput(input) => {
-                self.password_input = Some(input.clone());
-            }
-            In::Submit => {
-                let registration = self.get_login();
+            In::Submit { login, remember } => {
+                self.remember = *remember;
+                let login = login.clone();
                 sub.send_async(async {
-                    match api::auth_user(registration).await {
+                    match api::auth_user(login).await {
                         Ok(user) => In::LoginSuccess { user },
-                        Err(err) => In::LoginFailure { errors: Vec::from(err) },
+                        Err(err) => In::LoginFailure {
+                            errors: FieldErrors::from(err),
+                        },
                     }
                 });
             }
-            In::LoginSuccess { user } => match store::write_user(user) {
-                Ok(()) => {
-                    let location: Location = mogwai::utils::window().location();
-                    let _ = location.set_hash(&Route::Home.as_hash());
-                }
+            In::LoginSuccess { user } => match self.session.sign_in(user, self.remember) {
+                // The nav's route guard sends us on now that we're signed in.
+                Ok(()) => toast::success(format!("Welcome back, {}!", user.username)),
                 Err(err) => {
                     sub.send_async(async move {
                         In::LoginFailure {
-                            errors: vec![format!("{}", err)],
+                            errors: FieldErrors::other(format!("{}", err)),
                         }
                     });
                 }
             },
             In::LoginFailure { errors } => {
-                tx.send(&Out::Error(Patch::RemoveAll));
-                for error in errors.iter() {
-                    tx.send(&Out::Error(Patch::PushBack {
-                        value: view! {
-                            <li>{error}</li>
-                        },
-                    }));
-                }
+                self.form.send(&auth_form::In::Failure(errors.clone()));
             }
         }
     }
 
-    fn view(&self, tx: &Transmitter<In>, rx: &Receiver<Out>) -> ViewBuilder<HtmlElement> {
+    fn view(&self, _tx: &Transmitter<In>, _rx: &Receiver<()>) -> ViewBuilder<HtmlElement> {
         builder! {
             <div class="auth-page">
                 <div class="container page">
@@ -127,43 +96,7 @@ impl Component for Login {
                             <p class="text-xs-center">
                                 <a href="#/register">"Need an account?"</a>
                             </p>
-
-                            <ul class="error-messages"
-                                patch:children=rx.branch_filter_map(|msg| msg.errors())>
-                            </ul>
-
-                            <form>
-                                <fieldset class="form-group">
-                                    <input
-                                        cast:type = HtmlInputElement
-                                        class="form-control form-control-lg"
-                                        type="text"
-                                        placeholder="Email"
-                                        post:build=tx.contra_map(|el:&HtmlInputElement| {
-                                            In::EmailInput(el.clone())
-                                        })
-                                        />
-                                </fieldset>
-                                <fieldset class="form-group">
-                                    <input
-                                        cast:type = HtmlInputElement
-                                        class="form-control form-control-lg"
-                                        type="password"
-                                        placeholder="Password"
-                                        post:build=tx.contra_map(|el:&HtmlInputElement| {
-                                            In::PasswordInput(el.clone())
-                                        })
-                                        />
-                                </fieldset>
-                                <button
-                                    class="btn btn-lg btn-primary pull-xs-right"
-                                    on:click=tx.contra_map(|ev: &Event| {
-                                        ev.prevent_default();
-                                        In::Submit
-                                    })>
-                                    "Sign in"
-                                </button>
-                            </form>
+                            {self.form.view_builder()}
                         </div>
                     </div>
                 </div>
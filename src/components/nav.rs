@@ -4,92 +4,196 @@
 //! routes.
 #![allow(unused_braces)]
 use mogwai::prelude::*;
-use wasm_bindgen::UnwrapThrowExt;
-use web_sys::HashChangeEvent;
+use std::rc::Rc;
+use web_sys::{HashChangeEvent, StorageEvent};
 
-use crate::{api::User, route::Route, store};
+use crate::{
+    api::User,
+    route::{AuthRequirement, FeedQuery, Route},
+    router::{self, RoutingMode},
+    session::Session,
+    store, title,
+};
 
 pub struct Nav {
     pub current_route: Route,
     pub o_user: Option<User>,
+    session: Session,
 }
 
-impl Default for Nav {
-    fn default() -> Nav {
-        let current_route = Route::try_from(utils::window().location().href().unwrap_throw())
-            .unwrap_or_else(|_| Route::Home);
-        let o_user = store::read_user().ok();
+impl Nav {
+    pub fn new(session: Session) -> Nav {
+        let mut current_route = router::current_route();
+        let o_user = session.user();
+        if let Some(redirect) = current_route.guard(o_user.is_some()) {
+            router::redirect(&redirect);
+            current_route = redirect;
+        }
+        title::navigated(&current_route);
         Nav {
             current_route,
             o_user,
+            session,
         }
     }
-}
 
-fn signed_out_view_builder(
-    home_class: Effect<String>,
-    login_class: Effect<String>,
-    register_class: Effect<String>,
-) -> ViewBuilder<HtmlElement> {
-    builder! {
-        <ul class="nav navbar-nav pull-xs-right">
-            <li class="nav-item">
-                <a class=home_class href="#/">" Home"</a>
-            </li>
-            <li class="nav-item">
-            <a class=login_class href="#/login">" Sign in"</a>
-            </li>
-            <li class="nav-item">
-                <a class=register_class href="#/register">" Sign up"</a>
-            </li>
-        </ul>
+    /// Redirect away from the current route if the user's sign in has changed and they
+    /// may no longer see it.
+    fn guard_current_route(&self) {
+        let o_redirect = match self.current_route.auth_requirement() {
+            // Losing the session, eg. by logging out, isn't a reason to ask them to
+            // sign in again.
+            AuthRequirement::SignedIn if self.o_user.is_none() => Some(Route::Home {
+                query: FeedQuery::default(),
+            }),
+            _ => self.current_route.guard(self.o_user.is_some()),
+        };
+        if let Some(redirect) = o_redirect {
+            router::redirect(&redirect);
+        }
     }
 }
 
-fn signed_in_view_builder(
-    user: &User,
-    home_class: Effect<String>,
-    editor_class: Effect<String>,
-    settings_class: Effect<String>,
-    profile_class: Effect<String>,
-) -> ViewBuilder<HtmlElement> {
-    let o_image: Option<ViewBuilder<HtmlElement>> = user
-        .image
-        .as_ref()
-        .map(|image| {
-            if image.is_empty() {
-                None
-            } else {
-                Some(builder! { <img class="user-pic" src=image /> })
-            }
-        })
-        .flatten();
+/// An entry in the nav menu.
+struct NavItem {
+    label: String,
+    o_icon: Option<&'static str>,
+    o_image: Option<String>,
+    route: Route,
+    /// Who the item is shown to.
+    shown_to: AuthRequirement,
+    /// Whether the item is highlighted at the given route.
+    is_active: Rc<dyn Fn(&Route) -> bool>,
+}
 
-    builder! {
-        <ul class="nav navbar-nav pull-xs-right">
-            <li class="nav-item">
-                <a class=home_class href="#/">" Home"</a>
-            </li>
-            <li class="nav-item">
-            <a class=editor_class href="#/editor">
-                <i class="ion-compose"></i>
-                " New Post"
-                </a>
-            </li>
-            <li class="nav-item">
-            <a class=settings_class href="#/settings">
-                <i class="ion-gear-a"></i>
-                " Settings"
-                </a>
-            </li>
+impl NavItem {
+    fn new(
+        label: &str,
+        o_icon: Option<&'static str>,
+        route: Route,
+        shown_to: AuthRequirement,
+        is_active: fn(&Route) -> bool,
+    ) -> Self {
+        NavItem {
+            label: label.to_string(),
+            o_icon,
+            o_image: None,
+            route,
+            shown_to,
+            is_active: Rc::new(is_active),
+        }
+    }
+
+    /// The signed in user's own profile.
+    fn profile(user: &User) -> Self {
+        let username = user.username.clone();
+        NavItem {
+            label: user.username.clone(),
+            o_icon: None,
+            o_image: user.image.clone().filter(|image| !image.is_empty()),
+            route: Route::Profile {
+                username: user.username.clone(),
+                is_favorites: false,
+                query: FeedQuery::default(),
+            },
+            shown_to: AuthRequirement::SignedIn,
+            // Someone else's profile isn't ours.
+            is_active: Rc::new(move |route| match route {
+                Route::Profile { username: name, .. } => name == &username,
+                _ => false,
+            }),
+        }
+    }
+
+    fn is_shown(&self, is_signed_in: bool) -> bool {
+        match self.shown_to {
+            AuthRequirement::Anyone => true,
+            AuthRequirement::SignedIn => is_signed_in,
+            AuthRequirement::SignedOut => !is_signed_in,
+        }
+    }
+
+    fn view_builder(&self, route: &Route, rx: &Receiver<NavView>) -> ViewBuilder<HtmlElement> {
+        let is_active = self.is_active.clone();
+        let class: Effect<String> = (
+            link_class(is_active(route)),
+            rx.branch_filter_map(move |msg| msg.route().map(|r| link_class(is_active(&r)))),
+        )
+            .into();
+        let o_icon = self.o_icon.map(|icon| builder! { <i class=icon></i> });
+        let o_image = self
+            .o_image
+            .as_ref()
+            .map(|image| builder! { <img class="user-pic" src=image /> });
+
+        builder! {
             <li class="nav-item">
-                <a class=profile_class href=format!("#/profile/{}", user.username)>
+                <a class=class href=self.route.href()>
+                    {o_icon}
                     {o_image}
-                    {format!(" {}", user.username)}
+                    {format!(" {}", self.label)}
                 </a>
             </li>
-        </ul>
+        }
+    }
+}
+
+fn link_class(is_active: bool) -> String {
+    if is_active {
+        "nav-link active"
+    } else {
+        "nav-link"
     }
+    .to_string()
+}
+
+/// The nav menu items shown to the given user, in order.
+fn nav_items(o_user: Option<&User>) -> Vec<NavItem> {
+    vec![
+        Some(NavItem::new(
+            "Home",
+            None,
+            Route::Home {
+                query: FeedQuery::default(),
+            },
+            AuthRequirement::Anyone,
+            |route| matches!(route, Route::Home { .. }),
+        )),
+        Some(NavItem::new(
+            "New Post",
+            Some("ion-compose"),
+            Route::Editor { o_slug: None },
+            AuthRequirement::SignedIn,
+            |route| matches!(route, Route::Editor { .. }),
+        )),
+        Some(NavItem::new(
+            "Settings",
+            Some("ion-gear-a"),
+            Route::Settings,
+            AuthRequirement::SignedIn,
+            |route| matches!(route, Route::Settings),
+        )),
+        // Only a signed in user has a profile to link to.
+        o_user.map(NavItem::profile),
+        Some(NavItem::new(
+            "Sign in",
+            None,
+            Route::Login { o_return_to: None },
+            AuthRequirement::SignedOut,
+            |route| matches!(route, Route::Login { .. }),
+        )),
+        Some(NavItem::new(
+            "Sign up",
+            None,
+            Route::Register,
+            AuthRequirement::SignedOut,
+            |route| matches!(route, Route::Register),
+        )),
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|item| item.is_shown(o_user.is_some()))
+    .collect()
 }
 
 fn list_view(
@@ -97,53 +201,26 @@ fn list_view(
     o_user: Option<&User>,
     rx: &Receiver<NavView>,
 ) -> ViewBuilder<HtmlElement> {
-    let home_class: Effect<String> = (
-        route.nav_home_class(),
-        rx.branch_filter_map(|msg| msg.route().map(|r| r.nav_home_class())),
-    )
-        .into();
-    let editor_class: Effect<String> = (
-        route.nav_editor_class(),
-        rx.branch_filter_map(|msg| msg.route().map(|r| r.nav_editor_class())),
-    )
-        .into();
-    let settings_class: Effect<String> = (
-        route.nav_settings_class(),
-        rx.branch_filter_map(|msg| msg.route().map(|r| r.nav_settings_class())),
-    )
-        .into();
-    let register_class: Effect<String> = (
-        route.nav_register_class(),
-        rx.branch_filter_map(|msg| msg.route().map(|r| r.nav_register_class())),
-    )
-        .into();
-    let login_class: Effect<String> = (
-        route.nav_register_class(),
-        rx.branch_filter_map(|msg| msg.route().map(|r| r.nav_register_class())),
-    )
-        .into();
-    let profile_class: Effect<String> = (
-        route.nav_register_class(),
-        rx.branch_filter_map(|msg| msg.route().map(|r| r.nav_profile_class())),
-    )
-        .into();
-
-    if let Some(user) = o_user.as_ref() {
-        signed_in_view_builder(
-            user,
-            home_class.clone(),
-            editor_class.clone(),
-            settings_class.clone(),
-            profile_class,
-        )
-    } else {
-        signed_out_view_builder(home_class, login_class, register_class)
+    let items: Vec<ViewBuilder<HtmlElement>> = nav_items(o_user)
+        .iter()
+        .map(|item| item.view_builder(route, rx))
+        .collect();
+
+    builder! {
+        <ul class="nav navbar-nav pull-xs-right">
+            {items}
+        </ul>
     }
 }
 
 #[derive(Clone)]
 pub enum NavModel {
     HashChange(Route),
+    /// An in-app link was clicked in history routing mode.
+    Navigate(Route),
+    /// The stored user may have been changed by another tab.
+    StorageChange,
+    UserChange(Option<User>),
 }
 
 #[derive(Clone)]
@@ -173,26 +250,44 @@ impl Component for Nav {
     type ViewMsg = NavView;
     type DomNode = HtmlElement;
 
+    fn bind(&self, sub: &Subscriber<NavModel>) {
+        sub.subscribe_map(&self.session.changes(), |o_user| {
+            NavModel::UserChange(o_user.clone())
+        });
+    }
+
     fn update(&mut self, msg: &NavModel, tx: &Transmitter<NavView>, _sub: &Subscriber<NavModel>) {
         match msg {
             NavModel::HashChange(route) => {
-                if route != &self.current_route {
+                if let Some(redirect) = route.guard(self.o_user.is_some()) {
+                    // Any resulting change comes back to us as another `HashChange`.
+                    router::redirect(&redirect);
+                } else if route != &self.current_route {
                     self.current_route = route.clone();
-                    mogwai::utils::document().set_title(&route.as_title());
+                    title::navigated(route);
                     tx.send(&NavView::Route(route.clone()));
-
-                    let o_user = store::read_user().ok();
-                    if o_user != self.o_user {
-                        self.o_user = o_user;
-                        tx.send(&NavView::PatchListView(Patch::Replace {
-                            index: 1,
-                            value: View::from(list_view(
-                                &self.current_route,
-                                self.o_user.as_ref(),
-                                &tx.spawn_recv(),
-                            )),
-                        }));
-                    }
+                }
+            }
+            NavModel::Navigate(route) => {
+                // Any resulting change comes back to us as a `HashChange`.
+                router::navigate(route);
+            }
+            NavModel::StorageChange => {
+                // Any resulting change comes back to us as a `UserChange`.
+                self.session.reload();
+            }
+            NavModel::UserChange(o_user) => {
+                if o_user != &self.o_user {
+                    self.o_user = o_user.clone();
+                    tx.send(&NavView::PatchListView(Patch::Replace {
+                        index: 1,
+                        value: View::from(list_view(
+                            &self.current_route,
+                            self.o_user.as_ref(),
+                            &tx.spawn_recv(),
+                        )),
+                    }));
+                    self.guard_current_route();
                 }
             }
         }
@@ -206,9 +301,29 @@ impl Component for Nav {
                 class="navbar navbar-light"
                 post:build=tx.contra_map(move |_| NavModel::HashChange(route.clone()))
                 window:hashchange=tx.contra_filter_map(|ev:&Event| {
+                    if RoutingMode::current() != RoutingMode::Hash {
+                        return None;
+                    }
                     let hev = ev.dyn_ref::<HashChangeEvent>().unwrap().clone();
                     let route = Route::try_from(hev.new_url().as_str()).ok()?;
                     Some(NavModel::HashChange( route ))
+                })
+                window:popstate=tx.contra_filter_map(|_:&Event| {
+                    if RoutingMode::current() != RoutingMode::History {
+                        return None;
+                    }
+                    Some(NavModel::HashChange( router::current_route() ))
+                })
+                document:click=tx.contra_filter_map(|ev:&Event| {
+                    router::intercept_link(ev).map(NavModel::Navigate)
+                })
+                window:storage=tx.contra_filter_map(|ev:&Event| {
+                    // A `null` key means the whole storage area was cleared.
+                    let sev = ev.dyn_ref::<StorageEvent>()?;
+                    match sev.key() {
+                        Some(key) if !store::is_user_key(&key) => None,
+                        _ => Some(NavModel::StorageChange),
+                    }
                 })>
                 <div
                     class="container"
@@ -220,3 +335,65 @@ impl Component for Nav {
         }
     }
 }
+
+#[cfg(test)]
+mod nav_tests {
+    use super::*;
+
+    fn jake() -> User {
+        User {
+            email: "jake@jake.jake".into(),
+            token: "jwt.token.here".into(),
+            username: "jake".into(),
+            bio: None,
+            image: Some(String::new()),
+        }
+    }
+
+    fn labels(items: &[NavItem]) -> Vec<&str> {
+        items.iter().map(|item| item.label.as_str()).collect()
+    }
+
+    #[test]
+    fn menu_depends_on_sign_in() {
+        assert_eq!(labels(&nav_items(None)), vec!["Home", "Sign in", "Sign up"]);
+        assert_eq!(
+            labels(&nav_items(Some(&jake()))),
+            vec!["Home", "New Post", "Settings", "jake"]
+        );
+    }
+
+    #[test]
+    fn only_the_current_item_is_active() {
+        let routes = vec![
+            Route::Home {
+                query: FeedQuery::default(),
+            },
+            Route::Login { o_return_to: None },
+            Route::Register,
+        ];
+        for (item, route) in nav_items(None).iter().zip(routes.iter()) {
+            for other in routes.iter() {
+                assert_eq!((item.is_active)(other), other == route, "{}", item.label);
+            }
+        }
+    }
+
+    #[test]
+    fn profile_item_is_active_on_own_profile_only() {
+        let items = nav_items(Some(&jake()));
+        let profile = items.last().unwrap();
+        assert_eq!(profile.o_image, None);
+        assert!((profile.is_active)(&profile.route));
+        assert!((profile.is_active)(&Route::Profile {
+            username: "jake".into(),
+            is_favorites: true,
+            query: FeedQuery::default(),
+        }));
+        assert!(!(profile.is_active)(&Route::Profile {
+            username: "celeb".into(),
+            is_favorites: false,
+            query: FeedQuery::default(),
+        }));
+    }
+}
@@ -1,62 +1,197 @@
 use mogwai::prelude::*;
 
 use crate::{
-    api::{self, Article, Articles, UserProfile},
-    store,
+    api::{self, request, Articles, User, UserProfile},
+    page,
+    route::{FeedQuery, Route},
+    session::Session,
+    title::Title,
 };
 
-fn article_builder(article: &Article) -> ViewBuilder<HtmlElement> {
-    builder! {
-        <div class="article-preview">
-            <div class="article-meta">
-                <a href=""><img src=&article.author.image /></a>
-                <div class="info">
-                    <a href="" class="author">{&article.author.username}</a>
-                    <span class="date">"January 20th"</span>
-                </div>
-                <button class="btn btn-outline-primary btn-sm pull-xs-right">
-                    <i class="ion-heart"></i>
-                    {format!(" {}", article.favorites_count)}
-                </button>
-            </div>
-            <a href="" class="preview-link">
-                <h1>{&article.title}</h1>
-                <p>{&article.description}</p>
-                <span>"Read more..."</span>
-            </a>
-        </div>
-    }
-}
+/// The number of articles shown on each page of a profile's feed.
+const ARTICLES_PER_PAGE: u32 = 10;
 
 pub struct Profile {
     pub username: String,
     pub profile: Option<UserProfile>,
+    o_articles: Option<Articles>,
     pub is_favorites: bool,
     pub is_self: bool,
+    pub o_page: Option<u32>,
+    session: Session,
+    title: Title,
 }
 
 impl Profile {
-    pub fn new(username: String, is_favorites: bool) -> Self {
+    pub fn new(
+        username: String,
+        is_favorites: bool,
+        o_page: Option<u32>,
+        session: Session,
+        title: Title,
+    ) -> Self {
         Profile {
             profile: None,
-            is_self: store::read_user()
-                .map(|u| u.username == username)
-                .unwrap_or_else(|_| false),
+            o_articles: None,
+            is_self: session.is_user(&username),
             username,
             is_favorites,
+            o_page,
+            session,
+            title,
+        }
+    }
+
+    /// Show the given profile and articles from the start instead of fetching them.
+    pub fn with_preloaded(mut self, profile: UserProfile, articles: Articles) -> Self {
+        self.profile = Some(profile);
+        self.o_articles = Some(articles);
+        self
+    }
+
+    /// The route of one of this profile's article tabs.
+    fn tab_route(&self, is_favorites: bool) -> Route {
+        Route::Profile {
+            username: self.username.clone(),
+            is_favorites,
+            query: FeedQuery::default(),
         }
     }
+
+    fn tab_class(&self, is_favorites: bool) -> &'static str {
+        if is_favorites == self.is_favorites {
+            "nav-link active"
+        } else {
+            "nav-link"
+        }
+    }
+
+    /// The button to edit our own settings, or to follow someone else.
+    fn action_builder(&self) -> ViewBuilder<HtmlElement> {
+        if self.is_self {
+            builder! {
+                <a class="btn btn-sm btn-outline-secondary action-btn" href="#/settings">
+                    <i class="ion-gear-a"></i>
+                    " Edit Profile Settings"
+                </a>
+            }
+        } else {
+            let username = self
+                .profile
+                .as_ref()
+                .map(|profile| profile.username.as_str())
+                .unwrap_or(&self.username);
+            builder! {
+                <button class="btn btn-sm btn-outline-secondary action-btn">
+                    <i class="ion-plus-round"></i>
+                    {format!(" Follow {}", username)}
+                </button>
+            }
+        }
+    }
+
+    /// Fetch the profile and its articles.
+    fn fetch(&self, sub: &Subscriber<In>) {
+        let username = self.username.clone();
+        let o_token = self.session.token();
+        sub.send_async(async move {
+            let profile = api::get_profile(&username, o_token.as_deref())
+                .await
+                .unwrap_or_else(|e| panic!("could not get profile '{}': {}", username, e));
+            In::Profile(profile)
+        });
+        let username = self.username.clone();
+        let o_token = self.session.token();
+        let is_favorites = self.is_favorites;
+        let o_page = self.o_page;
+        sub.send_async(async move {
+            let articles = get_articles(&username, is_favorites, o_page, o_token.as_deref())
+                .await
+                .unwrap_or_else(|e| panic!("could not fetch articles '{}': {}", username, e));
+            In::Articles(articles)
+        })
+    }
+}
+
+/// Fetch a page of the articles someone wrote or favorited.
+async fn get_articles(
+    username: &str,
+    is_favorites: bool,
+    o_page: Option<u32>,
+    o_token: Option<&str>,
+) -> Result<Articles, request::Error> {
+    let (o_author, o_favorited, offset) = articles_query(username, is_favorites, o_page);
+    api::get_articles(
+        o_author,
+        None,
+        o_favorited,
+        Some(ARTICLES_PER_PAGE),
+        Some(offset),
+        o_token,
+    )
+    .await
+}
+
+/// The author, favoriter and offset to query a page of someone's articles with.
+fn articles_query(
+    username: &str,
+    is_favorites: bool,
+    o_page: Option<u32>,
+) -> (Option<&str>, Option<&str>, u32) {
+    let offset = o_page.unwrap_or(1).saturating_sub(1) * ARTICLES_PER_PAGE;
+    if is_favorites {
+        (None, Some(username), offset)
+    } else {
+        (Some(username), None, offset)
+    }
+}
+
+/// Warm the API cache with what a profile page shows.
+pub async fn prefetch(
+    username: String,
+    is_favorites: bool,
+    o_page: Option<u32>,
+    o_token: Option<String>,
+) {
+    let o_token = o_token.as_deref();
+    let _ = api::prefetch_profile(&username, o_token).await;
+    let (o_author, o_favorited, offset) = articles_query(&username, is_favorites, o_page);
+    let _ = api::prefetch_articles(
+        o_author,
+        None,
+        o_favorited,
+        Some(ARTICLES_PER_PAGE),
+        Some(offset),
+        o_token,
+    )
+    .await;
+}
+
+/// Fetch everything a profile page shows, so it can be shown complete.
+pub async fn load(
+    username: String,
+    is_favorites: bool,
+    o_page: Option<u32>,
+    o_token: Option<String>,
+) -> Result<(UserProfile, Articles), request::Error> {
+    let profile = api::get_profile(&username, o_token.as_deref()).await?;
+    let articles = get_articles(&username, is_favorites, o_page, o_token.as_deref()).await?;
+    Ok((profile, articles))
 }
 
 #[derive(Clone)]
 pub enum In {
     Articles(Articles),
     Profile(UserProfile),
+    /// Fetch the profile and its articles again, eg. when going back to the page.
+    Revalidate,
+    SessionChange(Option<User>),
 }
 
 #[derive(Clone)]
 pub enum Out {
     ArticlePatch(Patch<View<HtmlElement>>),
+    ActionPatch(Patch<View<HtmlElement>>),
     Profile(UserProfile),
 }
 
@@ -69,6 +204,14 @@ impl Out {
         }
     }
 
+    fn action(&self) -> Option<Patch<View<HtmlElement>>> {
+        if let Out::ActionPatch(patch) = self {
+            Some(patch.clone())
+        } else {
+            None
+        }
+    }
+
     fn user(&self) -> Option<UserProfile> {
         if let Out::Profile(user) = self {
             Some(user.clone())
@@ -84,44 +227,50 @@ impl Component for Profile {
     type DomNode = HtmlElement;
 
     fn bind(&self, sub: &Subscriber<Self::ModelMsg>) {
-        let username = self.username.clone();
-        let o_token = store::read_user().ok().map(|u| u.token);
-        sub.send_async(async move {
-            let profile = api::get_profile(&username, o_token.as_deref())
-                .await
-                .unwrap_or_else(|e| panic!("could not get profile '{}': {}", username, e));
-            In::Profile(profile)
+        sub.subscribe_map(&self.session.changes(), |o_user| {
+            In::SessionChange(o_user.clone())
         });
-        let username = self.username.clone();
-        let o_token = store::read_user().ok().map(|u| u.token);
-        sub.send_async(async move {
-            let articles =
-                api::get_articles(Some(&username), None, None, None, None, o_token.as_deref())
-                    .await
-                    .unwrap_or_else(|e| panic!("could not fetch articles '{}': {}", username, e));
-            In::Articles(articles)
-        })
+        match (self.profile.as_ref(), self.o_articles.as_ref()) {
+            (Some(profile), Some(_)) => self.title.set(&format!("@{}", profile.username)),
+            _ => self.fetch(sub),
+        }
     }
 
     fn update(
         &mut self,
         msg: &Self::ModelMsg,
         tx: &Transmitter<Self::ViewMsg>,
-        _sub: &Subscriber<Self::ModelMsg>,
+        sub: &Subscriber<Self::ModelMsg>,
     ) {
         match msg {
             In::Articles(articles) => {
+                self.o_articles = Some(articles.clone());
                 tx.send(&Out::ArticlePatch(Patch::RemoveAll));
                 for article in articles.articles.iter() {
                     tx.send(&Out::ArticlePatch(Patch::PushBack {
-                        value: View::from(article_builder(article)),
+                        value: View::from(page::article_preview(article)),
                     }));
                 }
             }
             In::Profile(user_profile) => {
-                self.is_self = user_profile.username == self.username;
+                self.title.set(&format!("@{}", user_profile.username));
                 self.profile = Some(user_profile.clone());
                 tx.send(&Out::Profile(user_profile.clone()));
+                tx.send(&Out::ActionPatch(Patch::Replace {
+                    index: 0,
+                    value: View::from(self.action_builder()),
+                }));
+            }
+            In::Revalidate => self.fetch(sub),
+            In::SessionChange(_) => {
+                let is_self = self.session.is_user(&self.username);
+                if is_self != self.is_self {
+                    self.is_self = is_self;
+                    tx.send(&Out::ActionPatch(Patch::Replace {
+                        index: 0,
+                        value: View::from(self.action_builder()),
+                    }));
+                }
             }
         }
     }
@@ -131,6 +280,12 @@ impl Component for Profile {
         _tx: &Transmitter<Self::ModelMsg>,
         rx: &Receiver<Self::ViewMsg>,
     ) -> ViewBuilder<HtmlElement> {
+        let articles: Vec<ViewBuilder<HtmlElement>> = self
+            .o_articles
+            .iter()
+            .flat_map(|articles| articles.articles.iter().map(page::article_preview))
+            .collect();
+
         builder! {
             <div class="profile-page">
                 <div class="user-info">
@@ -152,26 +307,9 @@ impl Component for Profile {
                                         rx.branch_filter_map(|msg| msg.user().map(|u| u.bio.unwrap_or_else(|| "".to_string())))
                                     )}
                                 </p>
-                                {if self.is_self {
-                                    builder! {
-                                        <a class="btn btn-sm btn-outline-secondary action-btn" href="#/settings">
-                                            <i class="ion-gear-a"></i>
-                                            " Edit Profile Settings"
-                                        </a>
-                                    }
-                                } else {
-                                    builder! {
-                                        <button class="btn btn-sm btn-outline-secondary action-btn">
-                                            <i class="ion-plus-round"></i>
-                                            {(
-                                                format!(" Follow {}", self.username),
-                                                rx.branch_filter_map(|msg| {
-                                                    msg.user().map(|u| format!(" Follow {}", u.username))
-                                                })
-                                            )}
-                                        </button>
-                                    }
-                                }}
+                                <slot patch:children=rx.branch_filter_map(|msg| msg.action())>
+                                    {self.action_builder()}
+                                </slot>
                             </div>
                         </div>
                     </div>
@@ -182,14 +320,19 @@ impl Component for Profile {
                             <div class="articles-toggle">
                                 <ul class="nav nav-pills outline-active">
                                     <li class="nav-item">
-                                        <a class="nav-link active" href="">"My Articles"</a>
+                                        <a class=self.tab_class(false) href=self.tab_route(false).href()>
+                                            "My Articles"
+                                        </a>
                                     </li>
                                     <li class="nav-item">
-                                        <a class="nav-link" href="">"Favorited Articles"</a>
+                                        <a class=self.tab_class(true) href=self.tab_route(true).href()>
+                                            "Favorited Articles"
+                                        </a>
                                     </li>
                                 </ul>
                             </div>
                             <slot patch:children=rx.branch_filter_map(|msg| msg.articles())>
+                                {articles}
                             </slot>
                         </div>
                     </div>
@@ -1,41 +1,99 @@
 use mogwai::prelude::*;
+use std::{cell::RefCell, rc::Rc};
 use web_sys::{HtmlInputElement, HtmlTextAreaElement};
 
 use crate::{
-    api::{self, User, UserUpdate},
-    route::Route,
-    store,
-    widgets::{TextInput, TextInputIn},
+    api::{self, request::FieldErrors, User, UserUpdate},
+    components::toast,
+    route::{FeedQuery, Route},
+    router,
+    session::Session,
+    widgets::{
+        field_builder, Confirm, ConfirmIn, FormValidity, SubmitButton, SubmitButtonMsg, TextInput,
+        TextInputIn, TextInputOut, Validator,
+    },
 };
 
 /// The settings UI component.
 pub struct Settings {
+    session: Session,
     o_user: Option<User>,
     pic_input: Gizmo<TextInput<HtmlInputElement>>,
     name_input: Gizmo<TextInput<HtmlInputElement>>,
     bio_input: Gizmo<TextInput<HtmlTextAreaElement>>,
     email_input: Gizmo<TextInput<HtmlInputElement>>,
     password_input: Gizmo<TextInput<HtmlInputElement>>,
+    confirm_input: Gizmo<TextInput<HtmlInputElement>>,
+    /// The new password, for checking the confirmation against.
+    new_password: Rc<RefCell<String>>,
+    validity: FormValidity,
+    submit_button: SubmitButton,
+    logout_confirm: Gizmo<Confirm>,
 }
 
-impl Default for Settings {
-    fn default() -> Self {
-        let mut settings = Settings {
-            pic_input: Gizmo::from(TextInput::new("", "URL of profile picture")),
-            name_input: Gizmo::from(TextInput::new("", "Your name")),
-            bio_input: Gizmo::from(TextInput::new("", "Short bio about you")),
-            email_input: Gizmo::from(TextInput::new("", "Your email")),
-            password_input: Gizmo::from(TextInput::new("", "Your password")),
-            o_user: None,
+impl Settings {
+    pub fn new(session: Session) -> Self {
+        let o_user = session.user();
+        // Start from the user's current values, so the form's validity is checked
+        // against what's shown.
+        let (image, username, bio, email) = match o_user.as_ref() {
+            Some(user) => (
+                user.image.clone().unwrap_or_default(),
+                user.username.clone(),
+                user.bio.clone().unwrap_or_default(),
+                user.email.clone(),
+            ),
+            None => Default::default(),
         };
-        if let Some(user) = store::read_user().ok() {
-            settings.set_user(user);
+        let new_password = Rc::new(RefCell::new(String::new()));
+        let pic_input = TextInput::new(&image, "URL of profile picture")
+            .with_validators("image", vec![Validator::Url]);
+        let name_input = TextInput::new(&username, "Your name").with_validators(
+            "username",
+            vec![Validator::Required, Validator::MaxLength(32)],
+        );
+        let email_input = TextInput::new(&email, "Your email")
+            .with_validators("email", vec![Validator::Required, Validator::Email]);
+        let password_input = TextInput::new("", "Your new password")
+            .with_type("password")
+            .with_validators("password", vec![Validator::MinLength(8)]);
+        let confirm_input = TextInput::new("", "Confirm your new password")
+            .with_type("password")
+            .with_validators(
+                "password confirmation",
+                vec![Validator::Matches {
+                    name: "password".to_string(),
+                    value: new_password.clone(),
+                }],
+            );
+        let mut validity = FormValidity::default();
+        validity.check(&pic_input);
+        validity.check(&name_input);
+        validity.check(&email_input);
+        validity.check(&password_input);
+        validity.check(&confirm_input);
+        Settings {
+            session,
+            pic_input: Gizmo::from(pic_input),
+            name_input: Gizmo::from(name_input),
+            bio_input: Gizmo::from(
+                TextInput::new(&bio, "Short bio about you").with_validators("bio", vec![]),
+            ),
+            email_input: Gizmo::from(email_input),
+            password_input: Gizmo::from(password_input),
+            confirm_input: Gizmo::from(confirm_input),
+            new_password,
+            validity,
+            submit_button: SubmitButton::new("Update Settings", "Updating Settings…"),
+            logout_confirm: Gizmo::from(Confirm::new(
+                "Log out?",
+                "You'll need to sign in again to write or comment.",
+                "Log out",
+            )),
+            o_user,
         }
-        settings
     }
-}
 
-impl Settings {
     fn set_user(&mut self, user: User) {
         if let Some(image) = user.image.as_ref() {
             self.pic_input.send(&TextInputIn::SetValue(image.to_string()));
@@ -52,21 +110,34 @@ impl Settings {
 #[derive(Clone)]
 pub enum In {
     GotUser(User),
+    SessionChange(Option<User>),
     Submit,
     UpdateSuccess(User),
-    UpdateFailure { errors: Vec<String> },
+    UpdateFailure { errors: FieldErrors },
+    Validity { name: String, is_valid: bool },
+    PasswordChange(String),
+    AskLogout,
     Logout,
 }
 
 #[derive(Clone)]
 pub enum Out {
     Error(Patch<View<HtmlElement>>),
+    Button(SubmitButtonMsg),
 }
 
 impl Out {
     fn errors(&self) -> Option<Patch<View<HtmlElement>>> {
         match self {
             Out::Error(patch) => Some(patch.clone()),
+            _ => None,
+        }
+    }
+
+    fn button(&self) -> Option<SubmitButtonMsg> {
+        match self {
+            Out::Button(msg) => Some(msg.clone()),
+            _ => None,
         }
     }
 }
@@ -77,6 +148,25 @@ impl Component for Settings {
     type DomNode = HtmlElement;
 
     fn bind(&self, sub: &Subscriber<In>) {
+        sub.subscribe_map(&self.session.changes(), |o_user| {
+            In::SessionChange(o_user.clone())
+        });
+        let validity = |msg: &TextInputOut| {
+            msg.validity()
+                .map(|(name, is_valid)| In::Validity { name, is_valid })
+        };
+        sub.subscribe_filter_map(&self.pic_input.recv, validity);
+        sub.subscribe_filter_map(&self.name_input.recv, validity);
+        sub.subscribe_filter_map(&self.email_input.recv, validity);
+        sub.subscribe_filter_map(&self.password_input.recv, validity);
+        sub.subscribe_filter_map(&self.confirm_input.recv, validity);
+        sub.subscribe_filter_map(&self.password_input.recv, |msg| {
+            msg.updated_value().map(In::PasswordChange)
+        });
+        sub.subscribe_filter_map(&self.logout_confirm.recv, |msg| match msg.answer() {
+            Some(true) => Some(In::Logout),
+            _ => None,
+        });
         if let Some(user) = self.o_user.as_ref() {
             let user = user.clone();
             sub.send_async(async move {
@@ -91,10 +181,19 @@ impl Component for Settings {
     fn update(&mut self, msg: &In, tx: &Transmitter<Out>, sub: &Subscriber<In>) {
         match msg {
             In::GotUser(user) => {
+                // The session only announces changes, so fill in the inputs ourselves
+                // now that they've been built.
                 self.set_user(user.clone());
-                let _ = store::write_user(user);
+                let _ = self.session.update_user(user);
             }
+            In::SessionChange(o_user) => match o_user {
+                Some(user) => self.set_user(user.clone()),
+                None => self.o_user = None,
+            },
             In::Submit => {
+                if !self.submit_button.can_submit(&self.validity) {
+                    return;
+                }
                 let email = Some(self.email_input.state.borrow().value.clone());
                 let username = Some(self.name_input.state.borrow().value.clone());
                 let bio = Some(self.bio_input.state.borrow().value.clone());
@@ -117,32 +216,48 @@ impl Component for Settings {
                         password,
                     };
                     let token = user.token.clone();
+                    tx.send(&Out::Button(self.submit_button.set_submitting(true)));
                     sub.send_async(async move {
                         match api::update_user(user_update, &token).await {
                             Ok(user) => In::UpdateSuccess(user),
                             Err(err) => In::UpdateFailure {
-                                errors: Vec::from(err),
+                                errors: FieldErrors::from(err),
                             },
                         }
                     })
                 }
             }
             In::UpdateSuccess(user) => {
-                self.o_user = Some(user.clone());
-
-                let _ = store::write_user(user);
-                let _ = mogwai::utils::window().location().set_hash(
-                    Route::Profile {
-                        username: user.username.clone(),
-                        is_favorites: false,
-                    }
-                    .as_hash()
-                    .as_ref(),
-                );
+                // This page is kept around to show again later.
+                tx.send(&Out::Button(self.submit_button.set_submitting(false)));
+                match self.session.update_user(user) {
+                    Ok(()) => toast::success("Your settings were saved."),
+                    Err(err) => toast::error(format!(
+                        "Your settings were saved, but not on this device: {}",
+                        err
+                    )),
+                }
+                router::navigate(&Route::Profile {
+                    username: user.username.clone(),
+                    is_favorites: false,
+                    query: FeedQuery::default(),
+                });
             }
             In::UpdateFailure { errors } => {
+                tx.send(&Out::Button(self.submit_button.set_submitting(false)));
+                self.pic_input
+                    .send(&TextInputIn::ServerErrors(errors.get("image")));
+                self.name_input
+                    .send(&TextInputIn::ServerErrors(errors.get("username")));
+                self.bio_input
+                    .send(&TextInputIn::ServerErrors(errors.get("bio")));
+                self.email_input
+                    .send(&TextInputIn::ServerErrors(errors.get("email")));
+                self.password_input
+                    .send(&TextInputIn::ServerErrors(errors.get("password")));
                 tx.send(&Out::Error(Patch::RemoveAll));
-                for error in errors.iter() {
+                let fields = ["image", "username", "bio", "email", "password"];
+                for error in errors.unshown(&fields).iter() {
                     tx.send(&Out::Error(Patch::PushBack {
                         value: view! {
                             <li>{error}</li>
@@ -150,16 +265,34 @@ impl Component for Settings {
                     }));
                 }
             }
+            In::Validity { name, is_valid } => {
+                self.validity.set(name, *is_valid);
+                tx.send(&Out::Button(SubmitButtonMsg::CanSubmit(
+                    self.validity.is_valid(),
+                )));
+            }
+            In::PasswordChange(password) => {
+                *self.new_password.borrow_mut() = password.clone();
+                self.confirm_input.send(&TextInputIn::Validate);
+            }
+            In::AskLogout => {
+                self.logout_confirm.send(&ConfirmIn::Open);
+            }
             In::Logout => {
-                let _ = store::delete_user();
-                let _ = mogwai::utils::window()
-                    .location()
-                    .set_hash(Route::Home.as_hash().as_ref());
+                // The nav's route guard sends us home now that we're signed out.
+                match self.session.sign_out() {
+                    Ok(()) => toast::info("You've logged out."),
+                    Err(err) => {
+                        toast::error(format!("Could not forget you on this device: {}", err))
+                    }
+                }
             }
         }
     }
 
     fn view(&self, tx: &Transmitter<In>, rx: &Receiver<Out>) -> ViewBuilder<HtmlElement> {
+        let button = rx.branch_filter_map(|msg| msg.button());
+        let is_submitting = button.branch_filter_map(|msg| msg.is_submitting());
         builder! {
             <div class="settings-page">
                 <div class="container page">
@@ -169,36 +302,27 @@ impl Component for Settings {
                             <ul class="error-messages"
                                 patch:children=rx.branch_filter_map(|msg| msg.errors())>
                             </ul>
-                            <form>
-                                <fieldset>
-                                    <fieldset class="form-group">
-                                        {self.pic_input.view_builder()}
-                                    </fieldset>
-                                    <fieldset class="form-group">
-                                        {self.name_input.view_builder()}
-                                    </fieldset>
-                                    <fieldset class="form-group">
-                                        {self.bio_input.view_builder()}
-                                    </fieldset>
-                                    <fieldset class="form-group">
-                                        {self.email_input.view_builder()}
-                                    </fieldset>
-                                    <fieldset class="form-group">
-                                        {self.password_input.view_builder()}
-                                    </fieldset>
-                                    <button
-                                        on:click=tx.contra_map(|_| In::Submit)
-                                        class="btn btn-lg btn-primary pull-xs-right">
-                                        "Update Settings"
-                                    </button>
+                            <form on:submit=tx.contra_map(|ev: &Event| {
+                                ev.prevent_default();
+                                In::Submit
+                            })>
+                                <fieldset boolean:disabled=(false, is_submitting)>
+                                    {field_builder(&self.pic_input)}
+                                    {field_builder(&self.name_input)}
+                                    {field_builder(&self.bio_input)}
+                                    {field_builder(&self.email_input)}
+                                    {field_builder(&self.password_input)}
+                                    {field_builder(&self.confirm_input)}
+                                    {self.submit_button.builder(self.validity.is_valid(), button)}
                                 </fieldset>
                             </form>
                             <hr />
                             <button
                                 class="btn btn-outline-danger"
-                                on:click=tx.contra_map(|_| In::Logout)>
+                                on:click=tx.contra_map(|_| In::AskLogout)>
                                 "Or click here to logout."
                             </button>
+                            {self.logout_confirm.view_builder()}
                         </div>
                     </div>
                 </div>
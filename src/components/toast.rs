@@ -0,0 +1,190 @@
+//! Short-lived notices telling the user how their actions went.
+//!
+//! Any component can send a notice with `info`, `success` or `error`. The app shows
+//! them stacked in a corner of the window, newest last, each dismissing itself after a
+//! while or when its close button is clicked.
+#![allow(unused_braces)]
+use mogwai::prelude::*;
+
+use crate::timer;
+
+/// How long info and success notices are shown for, in milliseconds.
+const SHORT_TIMEOUT: i32 = 4000;
+
+/// How long error notices are shown for, in milliseconds. Errors take longer to read
+/// and matter more.
+const LONG_TIMEOUT: i32 = 8000;
+
+/// How many notices are shown at once. The oldest are dismissed to make room.
+const MAX_SHOWN: usize = 4;
+
+thread_local! {
+    static NOTICES: Transmitter<Notice> = Transmitter::new();
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Level {
+    Info,
+    Success,
+    Error,
+}
+
+impl Level {
+    fn class(&self) -> &'static str {
+        match self {
+            Level::Info => "toast toast-info",
+            Level::Success => "toast toast-success",
+            Level::Error => "toast toast-error",
+        }
+    }
+
+    fn timeout(&self) -> i32 {
+        match self {
+            Level::Error => LONG_TIMEOUT,
+            _ => SHORT_TIMEOUT,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Notice {
+    pub level: Level,
+    pub message: String,
+}
+
+/// Show a notice.
+pub fn notify(notice: Notice) {
+    NOTICES.with(|tx| tx.send(&notice));
+}
+
+/// Show a neutral notice.
+pub fn info(message: impl Into<String>) {
+    notify(Notice {
+        level: Level::Info,
+        message: message.into(),
+    });
+}
+
+/// Show a notice that something worked.
+pub fn success(message: impl Into<String>) {
+    notify(Notice {
+        level: Level::Success,
+        message: message.into(),
+    });
+}
+
+/// Show a notice that something went wrong.
+pub fn error(message: impl Into<String>) {
+    notify(Notice {
+        level: Level::Error,
+        message: message.into(),
+    });
+}
+
+/// The stack of notices currently shown.
+pub struct Toasts {
+    next_id: u32,
+    /// The ids of the notices shown, oldest first.
+    shown: Vec<u32>,
+    tx_dismiss: Transmitter<u32>,
+}
+
+impl Default for Toasts {
+    fn default() -> Self {
+        Toasts {
+            next_id: 0,
+            shown: vec![],
+            tx_dismiss: Transmitter::new(),
+        }
+    }
+}
+
+impl Toasts {
+    fn toast_builder(&self, id: u32, notice: &Notice) -> ViewBuilder<HtmlElement> {
+        // errors interrupt, everything else waits its turn
+        let role = match notice.level {
+            Level::Error => "alert",
+            _ => "status",
+        };
+        builder! {
+            <div class=notice.level.class() role=role>
+                <span class="toast-message">{&notice.message}</span>
+                <button
+                    class="toast-close"
+                    type="button"
+                    aria-label="Dismiss"
+                    on:click=self.tx_dismiss.contra_map(move |_| id)>
+                    "×"
+                </button>
+            </div>
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum In {
+    Notice(Notice),
+    Dismiss(u32),
+}
+
+#[derive(Clone)]
+pub enum Out {
+    Patch(Patch<View<HtmlElement>>),
+}
+
+impl Out {
+    fn patch(&self) -> Option<Patch<View<HtmlElement>>> {
+        match self {
+            Out::Patch(patch) => Some(patch.clone()),
+        }
+    }
+}
+
+impl Component for Toasts {
+    type ModelMsg = In;
+    type ViewMsg = Out;
+    type DomNode = HtmlElement;
+
+    fn bind(&self, sub: &Subscriber<In>) {
+        sub.subscribe_map(&NOTICES.with(|tx| tx.spawn_recv()), |notice| {
+            In::Notice(notice.clone())
+        });
+        sub.subscribe_map(&self.tx_dismiss.spawn_recv(), |id| In::Dismiss(*id));
+    }
+
+    fn update(&mut self, msg: &In, tx: &Transmitter<Out>, sub: &Subscriber<In>) {
+        match msg {
+            In::Notice(notice) => {
+                if self.shown.len() >= MAX_SHOWN {
+                    self.shown.remove(0);
+                    tx.send(&Out::Patch(Patch::Remove { index: 0 }));
+                }
+                let id = self.next_id;
+                self.next_id = self.next_id.wrapping_add(1);
+                self.shown.push(id);
+                tx.send(&Out::Patch(Patch::PushBack {
+                    value: View::from(self.toast_builder(id, notice)),
+                }));
+                let timeout = notice.level.timeout();
+                sub.send_async(async move {
+                    timer::sleep(timeout).await;
+                    In::Dismiss(id)
+                });
+            }
+            In::Dismiss(id) => {
+                // it may have been dismissed already
+                if let Some(index) = self.shown.iter().position(|shown| shown == id) {
+                    self.shown.remove(index);
+                    tx.send(&Out::Patch(Patch::Remove { index }));
+                }
+            }
+        }
+    }
+
+    fn view(&self, _tx: &Transmitter<In>, rx: &Receiver<Out>) -> ViewBuilder<HtmlElement> {
+        builder! {
+            <div class="toasts" patch:children=rx.branch_filter_map(|msg| msg.patch())>
+            </div>
+        }
+    }
+}
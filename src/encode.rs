@@ -0,0 +1,45 @@
+//! Percent-encoding of the path segments and query values shared by routes and API
+//! requests.
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+
+/// Characters escaped in query values - everything that could end the value or be
+/// mistaken for another parameter. Slashes are left alone so return-to paths stay
+/// readable.
+const QUERY_VALUE: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'&')
+    .add(b'+')
+    .add(b'<')
+    .add(b'=')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b'\\')
+    .add(b']')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+/// Characters escaped in path segments, which also can't contain slashes.
+const SEGMENT: &AsciiSet = &QUERY_VALUE.add(b'/');
+
+/// Percent-encode a path segment, eg. a slug or username.
+pub fn encode_segment(segment: &str) -> String {
+    utf8_percent_encode(segment, SEGMENT).to_string()
+}
+
+/// Percent-encode a query string value.
+pub fn encode_query_value(value: &str) -> String {
+    utf8_percent_encode(value, QUERY_VALUE).to_string()
+}
+
+/// Percent-decode a path segment or query string value. Invalid UTF-8 is replaced
+/// rather than rejected.
+pub fn decode(encoded: &str) -> String {
+    percent_decode_str(encoded).decode_utf8_lossy().into_owned()
+}
@@ -5,18 +5,25 @@ use std::panic;
 use wasm_bindgen::prelude::*;
 
 mod api;
+mod cache;
 mod components;
+mod encode;
 mod page;
+mod prefetch;
 mod route;
+mod router;
+mod scroll;
+mod session;
 mod store;
+mod timer;
+mod title;
 mod widgets;
 
-use components::{
-    login::Login,
-    nav::Nav,
-    register::Register,
-};
+use cache::Lru;
+use components::{nav::Nav, toast::Toasts};
+use page::Page;
 use route::*;
+use session::Session;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
@@ -24,25 +31,87 @@ use route::*;
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
-pub enum Page {
-    Login(Gizmo<Login>),
-    Register(Gizmo<Register>),
-}
+/// How many recently visited pages are kept to be shown again.
+const PAGE_CACHE_SIZE: usize = 10;
+
+/// How long the old page is kept while the next one's data loads, in milliseconds.
+const LOAD_TIMEOUT: i32 = 2000;
 
 struct App {
+    session: Session,
     nav: Gizmo<Nav>,
+    toasts: Gizmo<Toasts>,
+    pages: Lru<Route, Page>,
+    /// The number of navigations so far, to tell apart loads for the same route.
+    navigations: u64,
+    /// The navigation whose data we're waiting on, if any.
+    o_loading: Option<u64>,
 }
 
 impl Default for App {
     fn default() -> App {
-        let nav = Gizmo::from(Nav::default());
-        App { nav }
+        let session = Session::load();
+        let nav = Gizmo::from(Nav::new(session.clone()));
+        prefetch::init(session.clone());
+        App {
+            session,
+            nav,
+            toasts: Gizmo::from(Toasts::default()),
+            pages: Lru::new(PAGE_CACHE_SIZE),
+            navigations: 0,
+            o_loading: None,
+        }
+    }
+}
+
+impl App {
+    /// The page for the given route, kept from an earlier visit if we're going back or
+    /// forward to it. Any other navigation builds it afresh, so following a link never
+    /// shows an old feed or draft.
+    fn page(&mut self, route: &Route, o_preloaded: Option<Preloaded>) -> Page {
+        if scroll::is_returning() {
+            if let Some(page) = self.pages.get(route) {
+                page.reshow();
+                return page.clone();
+            }
+        }
+        let page = route.page(&self.session, o_preloaded);
+        self.pages.insert(route.clone(), page.clone());
+        page
+    }
+
+    /// Swap in the page for the given route.
+    fn show(&mut self, route: &Route, o_preloaded: Option<Preloaded>, tx: &Transmitter<AppView>) {
+        self.o_loading = None;
+        tx.send(&AppView::Loading(false));
+        let page = self.page(route, o_preloaded);
+        tx.send(&AppView::NewPage {
+            page: page.view,
+            route: route.clone(),
+        });
+        scroll::page_shown();
     }
 }
 
 #[derive(Clone)]
 enum AppModel {
+    /// Show the first page.
+    Start,
     HashChange { route: Route },
+    /// A route's loader finished.
+    Loaded {
+        navigation: u64,
+        route: Route,
+        o_preloaded: Option<Preloaded>,
+    },
+    /// A route's loader took too long.
+    LoadTimedOut {
+        navigation: u64,
+        route: Route,
+    },
+    Scroll,
+    /// Kept pages may show someone else's data once the user changes.
+    SessionChange,
 }
 
 #[derive(Clone)]
@@ -51,6 +120,8 @@ enum AppView {
         page: View<HtmlElement>,
         route: Route,
     },
+    /// Show or hide the loading bar.
+    Loading(bool),
 }
 
 impl Component for App {
@@ -63,29 +134,87 @@ impl Component for App {
         sub.subscribe_filter_map(&self.nav.recv, |msg| msg.route().map(|r| AppModel::HashChange {
             route: r.clone()
         }));
+        sub.subscribe_map(&self.session.changes(), |_| AppModel::SessionChange);
     }
 
-    fn update(&mut self, msg: &AppModel, tx: &Transmitter<AppView>, _sub: &Subscriber<AppModel>) {
+    fn update(&mut self, msg: &AppModel, tx: &Transmitter<AppView>, sub: &Subscriber<AppModel>) {
         match msg {
-            AppModel::HashChange { route } => {
-                let page = View::from(route);
+            AppModel::Start => {
+                let route = self.nav.state_ref().current_route.clone();
+                let page = self.page(&route, None);
                 tx.send(&AppView::NewPage {
-                    page,
-                    route: route.clone(),
-                })
+                    page: page.view,
+                    route,
+                });
             }
+            AppModel::HashChange { route } => {
+                self.navigations += 1;
+                let navigation = self.navigations;
+                let o_loader = if scroll::is_returning() && self.pages.contains(route) {
+                    None
+                } else {
+                    route.loader(&self.session)
+                };
+                match o_loader {
+                    // keep the old page up until the new one has something to show
+                    Some(loader) => {
+                        self.o_loading = Some(navigation);
+                        tx.send(&AppView::Loading(true));
+                        let loaded_route = route.clone();
+                        sub.send_async(async move {
+                            AppModel::Loaded {
+                                navigation,
+                                route: loaded_route,
+                                o_preloaded: loader.await,
+                            }
+                        });
+                        let timed_out_route = route.clone();
+                        sub.send_async(async move {
+                            timer::sleep(LOAD_TIMEOUT).await;
+                            AppModel::LoadTimedOut {
+                                navigation,
+                                route: timed_out_route,
+                            }
+                        });
+                    }
+                    None => self.show(route, None, tx),
+                }
+            }
+            // Only the navigation we're still waiting on is shown, and only once. Going
+            // back to a route while its earlier load is pending starts a new navigation,
+            // so the earlier load's results are ignored.
+            AppModel::Loaded {
+                navigation,
+                route,
+                o_preloaded,
+            } => {
+                if self.o_loading == Some(*navigation) {
+                    self.show(route, o_preloaded.clone(), tx);
+                }
+            }
+            AppModel::LoadTimedOut { navigation, route } => {
+                if self.o_loading == Some(*navigation) {
+                    self.show(route, None, tx);
+                }
+            }
+            AppModel::Scroll => scroll::save(),
+            AppModel::SessionChange => self.pages.clear(),
         }
     }
 
-    fn view(&self, _: &Transmitter<AppModel>, rx: &Receiver<AppView>) -> ViewBuilder<HtmlElement> {
+    fn view(&self, tx: &Transmitter<AppModel>, rx: &Receiver<AppView>) -> ViewBuilder<HtmlElement> {
         builder! {
-            <slot patch:children=rx.branch_filter_map(|msg| match msg {
-                AppView::NewPage{ page, .. } => Some(Patch::Replace{ index: 1, value: page.clone() }),
-            })>
+            <slot
+                post:build=tx.contra_map(|_| AppModel::Start)
+                window:scroll=tx.contra_map(|_: &Event| AppModel::Scroll)
+                patch:children=rx.branch_filter_map(|msg| match msg {
+                    AppView::NewPage{ page, .. } => Some(Patch::Replace{ index: 1, value: page.clone() }),
+                    _ => None,
+                })>
                 {self.nav.view_builder()}
 
                 // This node gets replaced every time we send a patch from the parent node ^
-                {ViewBuilder::from(&self.nav.state_ref().current_route)}
+                <div></div>
 
                 <footer>
                     <div class="container">
@@ -97,6 +226,18 @@ impl Component for App {
                         </span>
                     </div>
                 </footer>
+
+                <div
+                    role="progressbar"
+                    aria-label="Loading"
+                    class=("loading-bar".to_string(), rx.branch_filter_map(|msg| match msg {
+                        AppView::Loading(true) => Some("loading-bar active".to_string()),
+                        AppView::Loading(false) => Some("loading-bar".to_string()),
+                        _ => None,
+                    }))>
+                </div>
+
+                {self.toasts.view_builder()}
             </slot>
         }
     }
@@ -106,6 +247,8 @@ impl Component for App {
 pub fn main() -> Result<(), JsValue> {
     panic::set_hook(Box::new(console_error_panic_hook::hook));
     console_log::init_with_level(Level::Trace).unwrap();
+    router::init(router::RoutingMode::detect());
+    scroll::init();
 
     View::from(Gizmo::from(App::default())).run()
 }
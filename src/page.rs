@@ -1,6 +1,174 @@
 use mogwai::prelude::*;
+use wasm_bindgen_futures::spawn_local;
 
-pub fn home() -> ViewBuilder<HtmlElement> {
+use crate::{
+    api::{self, Article},
+    route::{FeedQuery, Route},
+    title::Title,
+    widgets::TagInput,
+};
+
+/// The number of articles shown on each page of the home feed.
+const ARTICLES_PER_PAGE: u32 = 10;
+
+/// A page built for a route.
+///
+/// Pages are kept for a while after they're navigated away from, so going back can show
+/// them again just as they were left.
+#[derive(Clone)]
+pub struct Page {
+    pub view: View<HtmlElement>,
+    title: Title,
+    o_revalidate: Option<Transmitter<()>>,
+}
+
+impl Page {
+    pub fn new(view: View<HtmlElement>, title: Title) -> Self {
+        Page {
+            view,
+            title,
+            o_revalidate: None,
+        }
+    }
+
+    /// Refresh the page's data through the given transmitter whenever it's shown again.
+    pub fn with_revalidate(mut self, tx: Transmitter<()>) -> Self {
+        self.o_revalidate = Some(tx);
+        self
+    }
+
+    /// Called when the page is shown again.
+    pub fn reshow(&self) {
+        self.title.restore();
+        if let Some(tx) = self.o_revalidate.as_ref() {
+            tx.send(&());
+        }
+    }
+}
+
+/// A link to someone's profile.
+fn profile_href(username: &str) -> String {
+    Route::Profile {
+        username: username.to_string(),
+        is_favorites: false,
+        query: FeedQuery::default(),
+    }
+    .href()
+}
+
+/// A link to an article.
+fn article_href(slug: &str) -> String {
+    Route::Article {
+        slug: slug.to_string(),
+    }
+    .href()
+}
+
+/// A preview of an article in a feed, linking to it and its author.
+pub fn article_preview(article: &Article) -> ViewBuilder<HtmlElement> {
+    let author = profile_href(&article.author.username);
+    builder! {
+        <div class="article-preview">
+            <div class="article-meta">
+                <a href=&author><img src=&article.author.image /></a>
+                <div class="info">
+                    <a href=&author class="author">{&article.author.username}</a>
+                    <span class="date">"January 20th"</span>
+                </div>
+                <button class="btn btn-outline-primary btn-sm pull-xs-right">
+                    <i class="ion-heart"></i>
+                    {format!(" {}", article.favorites_count)}
+                </button>
+            </div>
+            <a href=article_href(&article.slug) class="preview-link">
+                <h1>{&article.title}</h1>
+                <p>{&article.description}</p>
+                <span>"Read more..."</span>
+            </a>
+        </div>
+    }
+}
+
+/// A link to the global feed filtered by a tag.
+fn tag_href(tag: &str) -> String {
+    Route::Home {
+        query: FeedQuery {
+            tag: Some(tag.to_string()),
+            ..FeedQuery::default()
+        },
+    }
+    .href()
+}
+
+/// The global feed, filtered by the query's tag and paged by its page.
+pub fn home(query: &FeedQuery, o_token: Option<String>) -> ViewBuilder<HtmlElement> {
+    let (tx_feed, rx_feed) = txrx::<Patch<View<HtmlElement>>>();
+    let o_tag = query.tag.clone();
+    let offset = query.page.unwrap_or(1).saturating_sub(1) * ARTICLES_PER_PAGE;
+    spawn_local(async move {
+        let result = api::get_articles(
+            None,
+            o_tag.as_deref(),
+            None,
+            Some(ARTICLES_PER_PAGE),
+            Some(offset),
+            o_token.as_deref(),
+        )
+        .await;
+        let previews = match result {
+            Ok(articles) if articles.articles.is_empty() => vec![builder! {
+                <div class="article-preview">"No articles are here... yet."</div>
+            }],
+            Ok(articles) => articles.articles.iter().map(article_preview).collect(),
+            Err(err) => vec![builder! {
+                <div class="article-preview">{format!("Could not load articles: {}", err)}</div>
+            }],
+        };
+        tx_feed.send(&Patch::RemoveAll);
+        for preview in previews {
+            tx_feed.send(&Patch::PushBack {
+                value: View::from(preview),
+            });
+        }
+    });
+
+    let global_href = Route::Home {
+        query: FeedQuery::default(),
+    }
+    .href();
+    let global_class = if query.tag.is_none() {
+        "nav-link active"
+    } else {
+        "nav-link"
+    };
+    let o_tag_tab = query.tag.as_ref().map(|tag| {
+        builder! {
+            <li class="nav-item">
+                <a class="nav-link active" href=tag_href(tag)>
+                    <i class="ion-pound"></i>
+                    {format!(" {}", tag)}
+                </a>
+            </li>
+        }
+    });
+    let popular = [
+        "programming",
+        "javascript",
+        "emberjs",
+        "angularjs",
+        "react",
+        "mean",
+        "node",
+        "rails",
+    ];
+    let tags = popular
+        .iter()
+        .map(|tag| {
+            builder! {
+                <a href=tag_href(tag) class="tag-pill tag-default">{*tag}</a>
+            }
+        })
+        .collect::<Vec<_>>();
     builder!(
         <div class="home-page">
             <div class="banner">
@@ -19,50 +187,17 @@ pub fn home() -> ViewBuilder<HtmlElement> {
                                     <a class="nav-link disabled" href="">"Your Feed"</a>
                                 </li>
                                 <li class="nav-item">
-                                    <a class="nav-link active" href="">"Global Feed"</a>
+                                    <a class=global_class href=global_href>
+                                        "Global Feed"
+                                    </a>
                                 </li>
+                                {o_tag_tab}
                             </ul>
                         </div>
 
-                        <div class="article-preview">
-                            <div class="article-meta">
-                                <a href="profile.html"><img src="http://i.imgur.com/Qr71crq.jpg" /></a>
-                                <div class="info">
-                                    <a href="" class="author">"Eric Simons"</a>
-                                    <span class="date">"January 20th"</span>
-                                </div>
-                                <button class="btn btn-outline-primary btn-sm pull-xs-right">
-                                    <i class="ion-heart"></i>
-                                    " 29"
-                                </button>
-                            </div>
-                            <a href="" class="preview-link">
-                                <h1>"How to build webapps that scale"</h1>
-                                <p>"This is the description for the post."</p>
-                                <span>"Read more..."</span>
-                            </a>
-                        </div>
-
-                        <div class="article-preview">
-                            <div class="article-meta">
-                                <a href="profile.html">
-                                   <img src="http://i.imgur.com/N4VcUeJ.jpg" />
-                                </a>
-                                <div class="info">
-                                    <a href="" class="author">"Albert Pai"</a>
-                                    <span class="date">"January 20th"</span>
-                                </div>
-                                <button class="btn btn-outline-primary btn-sm pull-xs-right">
-                                    <i class="ion-heart"></i>
-                                    " 32"
-                                </button>
-                            </div>
-                            <a href="" class="preview-link">
-                                <h1>"The song you won't ever stop singing. No matter how hard you try."</h1>
-                                <p>"This is the description for the post."</p>
-                                <span>"Read more..."</span>
-                            </a>
-                        </div>
+                        <slot patch:children=rx_feed>
+                            <div class="article-preview">"Loading articles..."</div>
+                        </slot>
                     </div>
 
                     <div class="col-md-3">
@@ -70,14 +205,7 @@ pub fn home() -> ViewBuilder<HtmlElement> {
                             <p>"Popular Tags"</p>
 
                             <div class="tag-list">
-                                <a href="" class="tag-pill tag-default">"programming"</a>
-                                <a href="" class="tag-pill tag-default">"javascript"</a>
-                                <a href="" class="tag-pill tag-default">"emberjs"</a>
-                                <a href="" class="tag-pill tag-default">"angularjs"</a>
-                                <a href="" class="tag-pill tag-default">"react"</a>
-                                <a href="" class="tag-pill tag-default">"mean"</a>
-                                <a href="" class="tag-pill tag-default">"node"</a>
-                                <a href="" class="tag-pill tag-default">"rails"</a>
+                                {tags}
                             </div>
                         </div>
                     </div>
@@ -88,6 +216,15 @@ pub fn home() -> ViewBuilder<HtmlElement> {
 }
 
 pub fn editor(_o_slug: &Option<String>) -> ViewBuilder<HtmlElement> {
+    // Tags are chosen with chips, and kept in a hidden input to be sent with the rest of
+    // the form. Tags can't contain commas, so they're joined with them.
+    let tag_input = Gizmo::from(TagInput::new(vec![]));
+    let tag_list = (
+        tag_input.state.borrow().tags.join(","),
+        tag_input
+            .recv
+            .branch_filter_map(|msg| msg.tags().map(|tags| tags.join(","))),
+    );
     builder! {
         <div class="editor-page">
             <div class="container page">
@@ -105,8 +242,8 @@ pub fn editor(_o_slug: &Option<String>) -> ViewBuilder<HtmlElement> {
                                     <textarea class="form-control" rows="8" placeholder="Write your article (in markdown)"></textarea>
                                 </fieldset>
                                 <fieldset class="form-group">
-                                    <input type="text" class="form-control" placeholder="Enter tags"/>
-                                    <div class="tag-list"></div>
+                                    {tag_input.view_builder()}
+                                    <input type="hidden" name="tagList" value=tag_list />
                                 </fieldset>
                                 <button class="btn btn-lg pull-xs-right btn-primary" type="button">
                                     "Publish Article"
@@ -120,67 +257,113 @@ pub fn editor(_o_slug: &Option<String>) -> ViewBuilder<HtmlElement> {
     }
 }
 
-pub fn article(_slug: &str) -> ViewBuilder<HtmlElement> {
+/// An article's author, date and actions, shown above and below it.
+fn article_meta(article: &Article) -> ViewBuilder<HtmlElement> {
+    let author = profile_href(&article.author.username);
     builder! {
-        <div class="article-page">
-            <div class="banner">
-                <div class="container">
-                    <h1>"How to build webapps that scale"</h1>
-                    <div class="article-meta">
-                        <a href=""><img src="http://i.imgur.com/Qr71crq.jpg" /></a>
-                        <div class="info">
-                            <a href="" class="author">"Eric Simons"</a>
-                            <span class="date">"January 20th"</span>
-                        </div>
-                        <button class="btn btn-sm btn-outline-secondary">
-                            <i class="ion-plus-round"></i>
-                            " Follow Eric Simons "
-                            <span class="counter">"(10)"</span>
-                        </button>
-                        " "" "
-                        <button class="btn btn-sm btn-outline-primary">
-                            <i class="ion-heart"></i>
-                            " Favorite Post "
-                            <span class="counter">"(29)"</span>
-                        </button>
-                    </div>
-                </div>
+        <div class="article-meta">
+            <a href=&author><img src=&article.author.image /></a>
+            <div class="info">
+                <a href=&author class="author">{&article.author.username}</a>
+                <span class="date">{article.created_at.format("%B %-d, %Y").to_string()}</span>
             </div>
+            <button class="btn btn-sm btn-outline-secondary">
+                <i class="ion-plus-round"></i>
+                {format!(" Follow {}", article.author.username)}
+            </button>
+            " "
+            <button class="btn btn-sm btn-outline-primary">
+                <i class="ion-heart"></i>
+                " Favorite Post "
+                <span class="counter">{format!("({})", article.favorites_count)}</span>
+            </button>
+        </div>
+    }
+}
 
-            <div class="container page">
-                <div class="row article-content">
-                    <div class="col-md-12">
-                        <p>
-                            "Web development technologies have evolved at an incredible clip over the past few years."
-                        </p>
-                        <h2 id="introducing-ionic">"Introducing RealWorld."</h2>
-                        <p>"It's a great solution for learning how other frameworks work."</p>
-                    </div>
+/// An article's body, split into paragraphs, with its tags and actions.
+fn article_content(article: &Article) -> ViewBuilder<HtmlElement> {
+    let paragraphs = article
+        .body
+        .split("\n\n")
+        .filter(|paragraph| !paragraph.trim().is_empty())
+        .map(|paragraph| builder! { <p>{paragraph}</p> })
+        .collect::<Vec<_>>();
+    let tags = article
+        .tag_list
+        .iter()
+        .map(|tag| builder! { <li class="tag-default tag-pill tag-outline">{tag}</li> })
+        .collect::<Vec<_>>();
+    builder! {
+        <div>
+            <div class="row article-content">
+                <div class="col-md-12">
+                    {paragraphs}
+                    <ul class="tag-list">
+                        {tags}
+                    </ul>
                 </div>
+            </div>
 
-                <hr />
+            <hr />
 
-                <div class="article-actions">
-                    <div class="article-meta">
-                        <a href="profile.html"><img src="http://i.imgur.com/Qr71crq.jpg" /></a>
-                        <div class="info">
-                            <a href="" class="author">"Eric Simons"</a>
-                            <span class="date">"January 20th"</span>
-                        </div>
+            <div class="article-actions">
+                {article_meta(article)}
+            </div>
+        </div>
+    }
+}
 
-                        <button class="btn btn-sm btn-outline-secondary">
-                            <i class="ion-plus-round"></i>
-                            " Follow Eric Simons "
-                            <span class="counter">"(10)"</span>
-                        </button>
-                        " "
-                        <button class="btn btn-sm btn-outline-primary">
-                            <i class="ion-heart"></i>
-                            " Favorite Post "
-                            <span class="counter">"(29)"</span>
-                        </button>
+/// The article page, built from a single fetch of the article that also sets the page's
+/// title.
+pub fn article(slug: &str, o_token: Option<String>, title: Title) -> ViewBuilder<HtmlElement> {
+    let (tx_banner, rx_banner) = txrx::<Patch<View<HtmlElement>>>();
+    let (tx_content, rx_content) = txrx::<Patch<View<HtmlElement>>>();
+    let slug = slug.to_string();
+    spawn_local(async move {
+        let (banner, content) = match api::get_article(&slug, o_token.as_deref()).await {
+            Ok(article) => {
+                title.set(&article.title);
+                let banner = builder! {
+                    <div class="container">
+                        <h1>{&article.title}</h1>
+                        {article_meta(&article)}
                     </div>
-                </div>
+                };
+                (banner, article_content(&article))
+            }
+            Err(err) => {
+                let banner = builder! {
+                    <div class="container">
+                        <h1>"Could not load the article"</h1>
+                    </div>
+                };
+                let content = builder! {
+                    <p>{format!("{}", err)}</p>
+                };
+                (banner, content)
+            }
+        };
+        for (tx, view) in vec![(tx_banner, banner), (tx_content, content)] {
+            tx.send(&Patch::RemoveAll);
+            tx.send(&Patch::PushBack {
+                value: View::from(view),
+            });
+        }
+    });
+
+    builder! {
+        <div class="article-page">
+            <div class="banner">
+                <slot patch:children=rx_banner>
+                    <div class="container">
+                        <h1>"Loading article..."</h1>
+                    </div>
+                </slot>
+            </div>
+
+            <div class="container page">
+                <slot patch:children=rx_content></slot>
 
                 <div class="row">
                     <div class="col-xs-12 col-md-8 offset-md-2">
@@ -237,3 +420,22 @@ pub fn article(_slug: &str) -> ViewBuilder<HtmlElement> {
         </div>
     }
 }
+
+pub fn not_found(path: &str) -> ViewBuilder<HtmlElement> {
+    let home = Route::Home {
+        query: FeedQuery::default(),
+    };
+    builder! {
+        <div class="not-found-page">
+            <div class="container page">
+                <div class="row">
+                    <div class="col-md-6 offset-md-3 col-xs-12 text-xs-center">
+                        <h1>"Page not found"</h1>
+                        <p>{format!("There's nothing at {}.", path)}</p>
+                        <a class="btn btn-lg btn-primary" href=home.href()>"Back to the home page"</a>
+                    </div>
+                </div>
+            </div>
+        </div>
+    }
+}
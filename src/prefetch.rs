@@ -0,0 +1,268 @@
+//! Fetching the data of pages the user is likely to visit next.
+//!
+//! Hovering or focusing a link to an article or profile, or scrolling one into view,
+//! prefetches that page's data into the API's cache so the page shows up quickly if the
+//! link is followed. At most `MAX_IN_FLIGHT` prefetches run at once, hovered links
+//! first, and nothing is prefetched when the browser asks us to save data.
+use mogwai::prelude::*;
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+use wasm_bindgen::{closure::Closure, JsValue};
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{
+    Element, IntersectionObserver, IntersectionObserverEntry, MutationObserver,
+    MutationObserverInit, MutationRecord,
+};
+
+use crate::{
+    cache::Lru,
+    route::{Prefetch, Route},
+    router,
+    session::Session,
+};
+
+/// How many prefetches may run at once.
+const MAX_IN_FLIGHT: usize = 2;
+
+/// How many prefetches may wait for their turn. When there are too many, the oldest
+/// links scrolled into view are dropped first, then the longest hovered.
+const MAX_QUEUED: usize = 16;
+
+/// How many recently prefetched routes are remembered, so they aren't fetched again.
+const REMEMBERED: usize = 64;
+
+/// How long a route isn't prefetched again for, in milliseconds. Matches how long the
+/// API keeps prefetched responses.
+const REFETCH_AFTER: f64 = 30_000.0;
+
+/// Prefetches waiting for their turn. The most recently hovered links go first, then
+/// links in the order they scrolled into view.
+struct Queue<T> {
+    urgent: VecDeque<T>,
+    background: VecDeque<T>,
+}
+
+impl<T> Queue<T> {
+    fn new() -> Self {
+        Queue {
+            urgent: VecDeque::new(),
+            background: VecDeque::new(),
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &T> {
+        self.urgent.iter().chain(self.background.iter())
+    }
+
+    /// Queue an item, dropping the oldest if there are more than `MAX_QUEUED`.
+    fn push(&mut self, item: T, is_urgent: bool) {
+        if is_urgent {
+            self.urgent.push_front(item);
+        } else {
+            self.background.push_back(item);
+        }
+        if self.urgent.len() + self.background.len() > MAX_QUEUED
+            && self.background.pop_front().is_none()
+        {
+            self.urgent.pop_back();
+        }
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        self.urgent
+            .pop_front()
+            .or_else(|| self.background.pop_front())
+    }
+}
+
+struct State {
+    session: Session,
+    in_flight: usize,
+    queue: Queue<(Route, Prefetch)>,
+    /// When routes were last prefetched.
+    prefetched: Lru<Route, f64>,
+}
+
+#[derive(Clone)]
+struct Prefetcher(Rc<RefCell<State>>);
+
+impl Prefetcher {
+    /// Queue a route's data to be prefetched. Urgent routes, eg. hovered links, go first.
+    fn request(&self, route: Route, is_urgent: bool) {
+        {
+            let mut state = self.0.borrow_mut();
+            let now = js_sys::Date::now();
+            let is_fresh = state
+                .prefetched
+                .get(&route)
+                .map(|at| now - at < REFETCH_AFTER)
+                .unwrap_or(false);
+            if is_fresh || state.queue.iter().any(|(queued, _)| queued == &route) {
+                return;
+            }
+            let prefetch = match route.prefetch(&state.session) {
+                Some(prefetch) => prefetch,
+                None => return,
+            };
+            state.queue.push((route, prefetch), is_urgent);
+        }
+        self.run();
+    }
+
+    /// Start queued prefetches while there's room in the budget.
+    fn run(&self) {
+        let mut state = self.0.borrow_mut();
+        while state.in_flight < MAX_IN_FLIGHT {
+            let (route, prefetch) = match state.queue.pop() {
+                Some(next) => next,
+                None => return,
+            };
+            state.prefetched.insert(route, js_sys::Date::now());
+            state.in_flight += 1;
+            let prefetcher = self.clone();
+            spawn_local(async move {
+                prefetch.await;
+                prefetcher.0.borrow_mut().in_flight -= 1;
+                prefetcher.run();
+            });
+        }
+    }
+}
+
+/// Whether the browser asks us to save data, eg. on a metered connection.
+fn saves_data() -> bool {
+    let navigator = js_sys::Reflect::get(&utils::window(), &JsValue::from("navigator"));
+    let connection =
+        navigator.and_then(|navigator| js_sys::Reflect::get(&navigator, &"connection".into()));
+    connection
+        .and_then(|connection| js_sys::Reflect::get(&connection, &"saveData".into()))
+        .ok()
+        .and_then(|save_data| save_data.as_bool())
+        .unwrap_or(false)
+}
+
+/// Watch the links in and under the given element scroll into view.
+fn observe_links(observer: &IntersectionObserver, root: &Element) {
+    if root.matches("a[href]").unwrap_or(false) {
+        observer.observe(root);
+    }
+    if let Ok(links) = root.query_selector_all("a[href]") {
+        for index in 0..links.length() {
+            if let Some(link) = links.item(index).and_then(|node| node.dyn_into().ok()) {
+                observer.observe(&link);
+            }
+        }
+    }
+}
+
+/// Start prefetching the pages of links the user may follow, unless they're saving data.
+pub fn init(session: Session) {
+    if saves_data() {
+        return;
+    }
+    let prefetcher = Prefetcher(Rc::new(RefCell::new(State {
+        session,
+        in_flight: 0,
+        queue: Queue::new(),
+        prefetched: Lru::new(REMEMBERED),
+    })));
+    let document = utils::document();
+
+    // hovered or focused links
+    let hovered = prefetcher.clone();
+    let on_hover = Closure::wrap(Box::new(move |ev: Event| {
+        if let Some(route) = router::event_link(&ev).and_then(|link| router::link_route(&link)) {
+            hovered.request(route, true);
+        }
+    }) as Box<dyn FnMut(Event)>);
+    for name in &["mouseover", "focusin"] {
+        let _ = document.add_event_listener_with_callback(name, on_hover.as_ref().unchecked_ref());
+    }
+    on_hover.forget();
+
+    // links scrolled into view
+    let on_visible = Closure::wrap(Box::new(
+        move |entries: js_sys::Array, observer: IntersectionObserver| {
+            for entry in entries.iter() {
+                let entry: IntersectionObserverEntry = entry.unchecked_into();
+                if entry.is_intersecting() {
+                    let link = entry.target();
+                    observer.unobserve(&link);
+                    if let Some(route) = router::link_route(&link) {
+                        prefetcher.request(route, false);
+                    }
+                }
+            }
+        },
+    ) as Box<dyn FnMut(js_sys::Array, IntersectionObserver)>);
+    let visibility = match IntersectionObserver::new(on_visible.as_ref().unchecked_ref()) {
+        Ok(observer) => observer,
+        Err(_) => return,
+    };
+    on_visible.forget();
+    let body = match document.body() {
+        Some(body) => body,
+        None => return,
+    };
+    observe_links(&visibility, &body);
+
+    // links added to the page later
+    let on_mutation = Closure::wrap(Box::new(
+        move |records: js_sys::Array, _observer: MutationObserver| {
+            for record in records.iter() {
+                let added = record.unchecked_into::<MutationRecord>().added_nodes();
+                for index in 0..added.length() {
+                    if let Some(element) = added.item(index).and_then(|node| node.dyn_into().ok()) {
+                        observe_links(&visibility, &element);
+                    }
+                }
+            }
+        },
+    ) as Box<dyn FnMut(js_sys::Array, MutationObserver)>);
+    if let Ok(mutations) = MutationObserver::new(on_mutation.as_ref().unchecked_ref()) {
+        let mut options = MutationObserverInit::new();
+        options.child_list(true).subtree(true);
+        let _ = mutations.observe_with_options(&body, &options);
+    }
+    on_mutation.forget();
+}
+
+#[cfg(test)]
+mod prefetch_tests {
+    use super::*;
+
+    fn drain(queue: &mut Queue<usize>) -> Vec<usize> {
+        std::iter::from_fn(|| queue.pop()).collect()
+    }
+
+    #[test]
+    fn hovered_links_go_first() {
+        let mut queue = Queue::new();
+        queue.push(1, false);
+        queue.push(2, true);
+        queue.push(3, false);
+        queue.push(4, true);
+        assert_eq!(drain(&mut queue), vec![4, 2, 1, 3]);
+    }
+
+    #[test]
+    fn overflow_drops_oldest_scrolled_into_view_first() {
+        let mut queue = Queue::new();
+        queue.push(100, true);
+        for item in 0..MAX_QUEUED {
+            queue.push(item, false);
+        }
+        let mut expected = vec![100];
+        expected.extend(1..MAX_QUEUED);
+        assert_eq!(drain(&mut queue), expected);
+    }
+
+    #[test]
+    fn overflow_drops_longest_hovered_when_nothing_else_is_queued() {
+        let mut queue = Queue::new();
+        for item in 0..=MAX_QUEUED {
+            queue.push(item, true);
+        }
+        let expected: Vec<usize> = (1..=MAX_QUEUED).rev().collect();
+        assert_eq!(drain(&mut queue), expected);
+    }
+}
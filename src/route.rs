@@ -1,15 +1,118 @@
 use log::trace;
 use mogwai::prelude::*;
+use std::{future::Future, pin::Pin};
 
 use crate::{
-    components::{login::Login, profile::Profile, register::Register, settings::Settings},
-    page,
+    api::{self, Articles, UserProfile},
+    components::{
+        login::Login,
+        profile::{self, Profile},
+        register::Register,
+        settings::Settings,
+    },
+    encode::{decode, encode_query_value, encode_segment},
+    page::{self, Page},
+    router::RoutingMode,
+    session::Session,
+    title::Title,
 };
 
+/// The optional query parameters of an article feed, eg. `?tab=feed&tag=rust&page=3`.
+///
+/// Keeping these in the route makes feed state bookmarkable and lets the back button
+/// restore it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeedQuery {
+    /// The selected feed tab, eg. `feed` for the signed-in user's own feed.
+    pub tab: Option<String>,
+    /// The tag the feed is filtered by.
+    pub tag: Option<String>,
+    /// The page of the feed, starting from 1.
+    pub page: Option<u32>,
+}
+
+impl FeedQuery {
+    /// Parse a query string, without its leading `?`. Unknown or malformed parameters
+    /// are ignored.
+    pub fn parse(query: &str) -> FeedQuery {
+        let mut feed_query = FeedQuery::default();
+        for (key, value) in query_params(query) {
+            match key {
+                "tab" if !value.is_empty() => feed_query.tab = Some(value),
+                "tag" if !value.is_empty() => feed_query.tag = Some(value),
+                "page" => feed_query.page = value.parse().ok(),
+                _ => {}
+            }
+        }
+        feed_query
+    }
+
+    /// The query string, including its leading `?`, or an empty string if there are no
+    /// parameters.
+    pub fn as_query_string(&self) -> String {
+        let params: Vec<String> = vec![
+            self.tab
+                .as_ref()
+                .map(|tab| format!("tab={}", encode_query_value(tab))),
+            self.tag
+                .as_ref()
+                .map(|tag| format!("tag={}", encode_query_value(tag))),
+            self.page.map(|page| format!("page={}", page)),
+        ]
+        .into_iter()
+        .filter_map(|x| x)
+        .collect();
+        if params.is_empty() {
+            String::new()
+        } else {
+            format!("?{}", params.join("&"))
+        }
+    }
+}
+
+/// The key/value pairs of a query string, without its leading `?`. Values are
+/// percent-decoded.
+fn query_params(query: &str) -> impl Iterator<Item = (&str, String)> {
+    query.split('&').map(|param| {
+        let mut key_value = param.splitn(2, '=');
+        let key = key_value.next().unwrap_or("");
+        let value = key_value.next().unwrap_or("");
+        (key, decode(value))
+    })
+}
+
+/// Data fetched for a route before its page is shown.
+#[derive(Clone)]
+pub enum Preloaded {
+    Profile {
+        profile: UserProfile,
+        articles: Articles,
+    },
+}
+
+/// Fetches a route's data. Resolves to `None` if the page should fetch it itself.
+pub type Loader = Pin<Box<dyn Future<Output = Option<Preloaded>>>>;
+
+/// Fetches a route's data into the API's cache ahead of time.
+pub type Prefetch = Pin<Box<dyn Future<Output = ()>>>;
+
+/// Who may visit a route.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AuthRequirement {
+    Anyone,
+    SignedIn,
+    SignedOut,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Route {
-    Home,
-    Login,
+    Home {
+        query: FeedQuery,
+    },
+    Login {
+        /// The path to go on to after signing in.
+        o_return_to: Option<String>,
+    },
     Register,
     Settings,
     Editor {
@@ -21,47 +124,27 @@ pub enum Route {
     Profile {
         username: String,
         is_favorites: bool,
+        query: FeedQuery,
+    },
+    /// Any path we don't have a page for.
+    NotFound {
+        path: String,
     },
 }
 
 impl TryFrom<&str> for Route {
     type Error = String;
 
+    /// Parse a route from a URL that keeps the route in its hash.
     fn try_from(s: &str) -> Result<Route, String> {
         trace!("route try_from: {}", s);
         // remove the scheme, if it has one
-        let hash_split = s.split("#").collect::<Vec<_>>();
+        let hash_split = s.splitn(2, "#").collect::<Vec<_>>();
         let after_hash = match hash_split.as_slice() {
             [_, after] => Ok(after),
             _ => Err(format!("route must have a hash: {}", s)),
         }?;
-
-        let paths: Vec<&str> = after_hash.split("/").collect::<Vec<_>>();
-        trace!("route paths: {:?}", paths);
-
-        match paths.as_slice() {
-            [""] => Ok(Route::Home),
-            ["", ""] => Ok(Route::Home),
-            ["", "login"] => Ok(Route::Login),
-            ["", "register"] => Ok(Route::Register),
-            ["", "settings"] => Ok(Route::Settings),
-            ["", "editor"] => Ok(Route::Editor { o_slug: None }),
-            ["", "editor", slug] => Ok(Route::Editor {
-                o_slug: Some(slug.to_string()),
-            }),
-            ["", "article", slug] => Ok(Route::Article {
-                slug: slug.to_string(),
-            }),
-            ["", "profile", username] => Ok(Route::Profile {
-                username: username.to_string(),
-                is_favorites: false,
-            }),
-            ["", "profile", username, "favorites"] => Ok(Route::Profile {
-                username: username.to_string(),
-                is_favorites: true,
-            }),
-            r => Err(format!("unsupported route: {:?}", r)),
-        }
+        Ok(Route::from_path(after_hash))
     }
 }
 
@@ -73,117 +156,232 @@ impl TryFrom<String> for Route {
     }
 }
 
-impl From<&Route> for ViewBuilder<HtmlElement> {
-    fn from(route: &Route) -> Self {
-        match route {
-            Route::Home => page::home().into(),
-            Route::Login => Gizmo::from(Login::default()).view_builder(),
-            Route::Register => {
-                let register = Gizmo::from(Register::default());
-                register.view_builder()
-            }
-            Route::Settings => Gizmo::from(Settings::default()).view_builder(),
-            Route::Editor { o_slug } => page::editor(o_slug).into(),
-            Route::Article { slug } => page::article(slug).into(),
-            Route::Profile {
-                username,
-                is_favorites,
-            } => Gizmo::from(Profile::new(username.to_string(), *is_favorites)).view_builder(),
-        }
-    }
-}
+impl Route {
+    /// Parse a route from its path and optional query string, eg.
+    /// `/profile/jake?tab=favorites`. Trailing slashes and any trailing fragment are
+    /// ignored.
+    ///
+    /// Paths we don't have a page for become `Route::NotFound`.
+    pub fn from_path(full_path: &str) -> Route {
+        let full_path = full_path.splitn(2, '#').next().unwrap_or("");
+        let mut path_query = full_path.splitn(2, '?');
+        let path = path_query.next().unwrap_or("");
+        let query_str = path_query.next().unwrap_or("");
+        let mut query = FeedQuery::parse(query_str);
 
-impl From<&Route> for View<HtmlElement> {
-    fn from(route: &Route) -> Self {
-        ViewBuilder::from(route).into()
-    }
-}
+        let paths: Vec<&str> = path.trim_end_matches('/').split("/").collect::<Vec<_>>();
+        trace!("route paths: {:?} query: {:?}", paths, query);
 
-impl Route {
-    pub fn nav_home_class(&self) -> String {
-        match self {
-            Route::Home => "nav-link active",
-            _ => "nav-link",
+        match paths.as_slice() {
+            [""] => Route::Home { query },
+            ["", ""] => Route::Home { query },
+            ["", "login"] => Route::Login {
+                o_return_to: query_params(query_str)
+                    .find(|(key, value)| *key == "redirect" && !value.is_empty())
+                    .map(|(_, value)| value),
+            },
+            ["", "register"] => Route::Register,
+            ["", "settings"] => Route::Settings,
+            ["", "editor"] => Route::Editor { o_slug: None },
+            ["", "editor", slug] => Route::Editor {
+                o_slug: Some(decode(slug)),
+            },
+            ["", "article", slug] => Route::Article { slug: decode(slug) },
+            ["", "profile", username] => {
+                // `?tab=favorites` is another way of writing `/favorites`
+                let is_favorites = query.tab.as_deref() == Some("favorites");
+                if is_favorites {
+                    query.tab = None;
+                }
+                Route::Profile {
+                    username: decode(username),
+                    is_favorites,
+                    query,
+                }
+            }
+            ["", "profile", username, "favorites"] => Route::Profile {
+                username: decode(username),
+                is_favorites: true,
+                query,
+            },
+            _ => Route::NotFound {
+                path: full_path.to_string(),
+            },
         }
-        .to_string()
     }
 
-    pub fn nav_editor_class(&self) -> String {
+    /// Who may visit this route.
+    pub fn auth_requirement(&self) -> AuthRequirement {
         match self {
-            Route::Editor { .. } => "nav-link active",
-            _ => "nav-link",
+            Route::Settings | Route::Editor { .. } => AuthRequirement::SignedIn,
+            Route::Login { .. } | Route::Register => AuthRequirement::SignedOut,
+            _ => AuthRequirement::Anyone,
         }
-        .to_string()
     }
 
-    pub fn nav_settings_class(&self) -> String {
-        match self {
-            Route::Settings { .. } => "nav-link active",
-            _ => "nav-link",
+    /// Where to send someone who navigates to this route but may not visit it, if
+    /// anywhere.
+    ///
+    /// Anonymous users are sent to sign in, returning here afterwards. Signed-in users
+    /// are sent on from the sign in and sign up pages to where they were going.
+    pub fn guard(&self, is_signed_in: bool) -> Option<Route> {
+        match (self.auth_requirement(), is_signed_in) {
+            (AuthRequirement::SignedIn, false) => Some(Route::Login {
+                o_return_to: Some(self.as_hash()),
+            }),
+            (AuthRequirement::SignedOut, true) => {
+                let o_return_to = match self {
+                    Route::Login { o_return_to } => o_return_to.as_deref(),
+                    _ => None,
+                };
+                let next = o_return_to
+                    .map(Route::from_path)
+                    // never bounce back to sign in or sign up
+                    .filter(|route| route.auth_requirement() != AuthRequirement::SignedOut)
+                    .unwrap_or_else(|| Route::Home {
+                        query: FeedQuery::default(),
+                    });
+                Some(next)
+            }
+            _ => None,
         }
-        .to_string()
     }
 
-    pub fn nav_register_class(&self) -> String {
+    /// Start fetching the data this route's page shows, if its page would otherwise jump
+    /// around as the data arrives.
+    pub fn loader(&self, session: &Session) -> Option<Loader> {
         match self {
-            Route::Register => "nav-link active",
-            _ => "nav-link",
+            Route::Profile {
+                username,
+                is_favorites,
+                query,
+            } => {
+                let load =
+                    profile::load(username.clone(), *is_favorites, query.page, session.token());
+                Some(Box::pin(async move {
+                    let (profile, articles) = load.await.ok()?;
+                    Some(Preloaded::Profile { profile, articles })
+                }))
+            }
+            _ => None,
         }
-        .to_string()
     }
 
-    pub fn nav_login_class(&self) -> String {
+    /// Start warming the API cache with this route's data, if it's worth prefetching.
+    pub fn prefetch(&self, session: &Session) -> Option<Prefetch> {
         match self {
-            Route::Login => "nav-link active",
-            _ => "nav-link",
+            Route::Article { slug } => {
+                let slug = slug.clone();
+                let o_token = session.token();
+                Some(Box::pin(async move {
+                    let _ = api::prefetch_article(&slug, o_token.as_deref()).await;
+                }))
+            }
+            Route::Profile {
+                username,
+                is_favorites,
+                query,
+            } => Some(Box::pin(profile::prefetch(
+                username.clone(),
+                *is_favorites,
+                query.page,
+                session.token(),
+            ))),
+            _ => None,
         }
-        .to_string()
     }
 
-    pub fn nav_profile_class(&self) -> String {
-        match self {
-            Route::Profile { .. } => "nav-link active",
-            _ => "nav-link",
-        }
-        .to_string()
+    /// Build the page for this route, from its preloaded data if there is any.
+    pub fn page(&self, session: &Session, o_preloaded: Option<Preloaded>) -> Page {
+        let title = Title::current();
+        let builder = match self {
+            Route::Home { query } => page::home(query, session.token()),
+            Route::Login { .. } => Gizmo::from(Login::new(session.clone())).view_builder(),
+            Route::Register => {
+                let register = Gizmo::from(Register::new(session.clone()));
+                register.view_builder()
+            }
+            Route::Settings => Gizmo::from(Settings::new(session.clone())).view_builder(),
+            Route::Editor { o_slug } => page::editor(o_slug),
+            Route::Article { slug } => page::article(slug, session.token(), title.clone()),
+            Route::NotFound { path } => page::not_found(path),
+            Route::Profile {
+                username,
+                is_favorites,
+                query,
+            } => {
+                let mut component = Profile::new(
+                    username.to_string(),
+                    *is_favorites,
+                    query.page,
+                    session.clone(),
+                    title.clone(),
+                );
+                if let Some(Preloaded::Profile { profile, articles }) = o_preloaded {
+                    component = component.with_preloaded(profile, articles);
+                }
+                let profile = Gizmo::from(component);
+                let revalidate = profile.trns.contra_map(|_: &()| profile::In::Revalidate);
+                return Page::new(View::from(profile.view_builder()), title)
+                    .with_revalidate(revalidate);
+            }
+        };
+        Page::new(View::from(builder), title)
     }
 
     pub fn as_title(&self) -> String {
         match self {
-            Route::Home => "Home".into(),
+            Route::Home { .. } => "Home".into(),
             Route::Register => "Sign Up".into(),
-            Route::Login => "Sign In".into(),
+            Route::Login { .. } => "Sign In".into(),
             Route::Editor { .. } => "Editor".into(),
             Route::Settings => "Settings".into(),
             Route::Article { .. } => "Article".into(),
             Route::Profile { .. } => "Profile".into(),
+            Route::NotFound { .. } => "Not Found".into(),
+        }
+    }
+
+    /// The href of a link to this route in the current routing mode.
+    pub fn href(&self) -> String {
+        match RoutingMode::current() {
+            RoutingMode::Hash => format!("#{}", self.as_hash()),
+            RoutingMode::History => self.as_hash(),
         }
     }
 
+    /// The path and query string of this route, which is kept after the hash in hash
+    /// routing mode.
     pub fn as_hash(&self) -> String {
         match self {
-            Route::Home => "/".into(),
+            Route::Home { query } => format!("/{}", query.as_query_string()),
             Route::Register => "/register".into(),
-            Route::Login => "/login".into(),
+            Route::Login { o_return_to } => match o_return_to {
+                Some(return_to) => format!("/login?redirect={}", encode_query_value(return_to)),
+                None => "/login".into(),
+            },
             Route::Editor { o_slug } => {
                 if let Some(slug) = o_slug {
-                    format!("/editor/{}", slug)
+                    format!("/editor/{}", encode_segment(slug))
                 } else {
                     "/editor".into()
                 }
             }
             Route::Settings => "/settings".into(),
-            Route::Article { slug } => format!("/article/{}", slug),
+            Route::Article { slug } => format!("/article/{}", encode_segment(slug)),
             Route::Profile {
                 username,
                 is_favorites,
+                query,
             } => {
+                let username = encode_segment(username);
                 if *is_favorites {
-                    format!("/profile/{}/favorites", username)
+                    format!("/profile/{}/favorites{}", username, query.as_query_string())
                 } else {
-                    format!("/profile/{}", username)
+                    format!("/profile/{}{}", username, query.as_query_string())
                 }
             }
+            Route::NotFound { path } => path.clone(),
         }
     }
 }
@@ -195,6 +393,277 @@ mod route_tests {
     #[test]
     fn can_convert_string_to_route() {
         let s = "https://localhost:8080/#/";
-        assert_eq!(Route::try_from(s), Ok(Route::Home));
+        assert_eq!(
+            Route::try_from(s),
+            Ok(Route::Home {
+                query: FeedQuery::default()
+            })
+        );
+    }
+
+    #[test]
+    fn can_convert_query_string_to_route() {
+        let s = "https://localhost:8080/#/?tag=rust&page=3";
+        let route = Route::Home {
+            query: FeedQuery {
+                tab: None,
+                tag: Some("rust".into()),
+                page: Some(3),
+            },
+        };
+        assert_eq!(Route::try_from(s), Ok(route.clone()));
+        assert_eq!(route.as_hash(), "/?tag=rust&page=3");
+
+        let s = "https://localhost:8080/#/profile/jake?tab=favorites#articles";
+        let route = Route::Profile {
+            username: "jake".into(),
+            is_favorites: true,
+            query: FeedQuery::default(),
+        };
+        assert_eq!(Route::try_from(s), Ok(route.clone()));
+        assert_eq!(route.as_hash(), "/profile/jake/favorites");
+    }
+
+    #[test]
+    fn can_convert_path_to_route() {
+        assert_eq!(
+            Route::from_path("/article/how-to-train-your-dragon"),
+            Route::Article {
+                slug: "how-to-train-your-dragon".into()
+            }
+        );
+    }
+
+    #[test]
+    fn anonymous_users_must_sign_in_first() {
+        assert_eq!(
+            Route::Settings.guard(false),
+            Some(Route::Login {
+                o_return_to: Some("/settings".into())
+            })
+        );
+        assert_eq!(Route::Settings.guard(true), None);
+        assert_eq!(Route::Register.guard(false), None);
+    }
+
+    #[test]
+    fn signed_in_users_skip_signing_in() {
+        let login = Route::try_from("https://localhost:8080/#/login?redirect=/editor").unwrap();
+        assert_eq!(login.guard(true), Some(Route::Editor { o_slug: None }));
+
+        let login = Route::Login {
+            o_return_to: Some("/register".into()),
+        };
+        assert_eq!(
+            login.guard(true),
+            Some(Route::Home {
+                query: FeedQuery::default()
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_paths_are_not_found() {
+        let s = "https://localhost:8080/#/article";
+        let route = Route::NotFound {
+            path: "/article".into(),
+        };
+        assert_eq!(Route::try_from(s), Ok(route.clone()));
+        assert_eq!(route.as_hash(), "/article");
+    }
+
+    fn hash_url(route: &Route) -> String {
+        format!("https://localhost:8080/#{}", route.as_hash())
+    }
+
+    /// One of every kind of route.
+    fn every_route() -> Vec<Route> {
+        vec![
+            Route::Home {
+                query: FeedQuery::default(),
+            },
+            Route::Home {
+                query: FeedQuery {
+                    tab: Some("feed".into()),
+                    tag: Some("dragons".into()),
+                    page: Some(2),
+                },
+            },
+            Route::Login { o_return_to: None },
+            Route::Login {
+                o_return_to: Some("/settings".into()),
+            },
+            Route::Register,
+            Route::Settings,
+            Route::Editor { o_slug: None },
+            Route::Editor {
+                o_slug: Some("how-to-train-your-dragon".into()),
+            },
+            Route::Article {
+                slug: "how-to-train-your-dragon".into(),
+            },
+            Route::Profile {
+                username: "jake".into(),
+                is_favorites: false,
+                query: FeedQuery::default(),
+            },
+            Route::Profile {
+                username: "jake".into(),
+                is_favorites: true,
+                query: FeedQuery {
+                    tab: None,
+                    tag: None,
+                    page: Some(3),
+                },
+            },
+            Route::NotFound {
+                path: "/nowhere/at/all".into(),
+            },
+        ]
+    }
+
+    #[test]
+    fn every_route_round_trips() {
+        for route in every_route() {
+            assert_eq!(Route::try_from(hash_url(&route)), Ok(route.clone()));
+            assert_eq!(Route::from_path(&route.as_hash()), route);
+        }
+    }
+
+    #[test]
+    fn trailing_slashes_are_ignored() {
+        assert_eq!(Route::from_path("/settings/"), Route::Settings);
+        assert_eq!(
+            Route::from_path("/profile/jake/favorites/"),
+            Route::Profile {
+                username: "jake".into(),
+                is_favorites: true,
+                query: FeedQuery::default(),
+            }
+        );
+        assert_eq!(
+            Route::from_path("//"),
+            Route::Home {
+                query: FeedQuery::default()
+            }
+        );
+    }
+
+    #[test]
+    fn percent_encoded_segments_round_trip() {
+        let route = Route::from_path("/profile/jake%20smith");
+        assert_eq!(
+            route,
+            Route::Profile {
+                username: "jake smith".into(),
+                is_favorites: false,
+                query: FeedQuery::default(),
+            }
+        );
+        assert_eq!(route.as_hash(), "/profile/jake%20smith");
+
+        let route = Route::Article {
+            slug: "why/how?#100%".into(),
+        };
+        assert_eq!(route.as_hash(), "/article/why%2Fhow%3F%23100%25");
+        assert_eq!(Route::from_path(&route.as_hash()), route);
+    }
+
+    #[test]
+    fn query_values_are_percent_encoded() {
+        let route = Route::Home {
+            query: FeedQuery {
+                tab: None,
+                tag: Some("c++ & rust".into()),
+                page: None,
+            },
+        };
+        assert_eq!(route.as_hash(), "/?tag=c%2B%2B%20%26%20rust");
+        assert_eq!(Route::from_path(&route.as_hash()), route);
+
+        let login = Route::Login {
+            o_return_to: Some("/?tab=feed&page=2".into()),
+        };
+        assert_eq!(login.as_hash(), "/login?redirect=/%3Ftab%3Dfeed%26page%3D2");
+        assert_eq!(Route::from_path(&login.as_hash()), login);
+    }
+
+    #[test]
+    fn unknown_paths_keep_their_path() {
+        for path in &[
+            "/article",
+            "/articles/slug",
+            "/profile",
+            "/settings/extra",
+            "/x?y=z",
+        ] {
+            assert_eq!(
+                Route::from_path(path),
+                Route::NotFound {
+                    path: path.to_string()
+                }
+            );
+        }
+    }
+
+    mod properties {
+        use super::super::*;
+        use proptest::{option, prelude::*};
+
+        fn slug() -> impl Strategy<Value = String> {
+            prop_oneof!["[a-z0-9][a-z0-9-]{0,40}", "\\PC{1,40}"]
+        }
+
+        fn username() -> impl Strategy<Value = String> {
+            prop_oneof!["[A-Za-z0-9_.]{1,20}", "\\PC{1,20}"]
+        }
+
+        fn feed_query() -> impl Strategy<Value = FeedQuery> {
+            (
+                option::of(prop_oneof![
+                    Just("feed".to_string()),
+                    Just("global".to_string())
+                ]),
+                option::of("\\PC{1,12}"),
+                option::of(1..1000u32),
+            )
+                .prop_map(|(tab, tag, page)| FeedQuery { tab, tag, page })
+        }
+
+        fn route() -> impl Strategy<Value = Route> {
+            prop_oneof![
+                feed_query().prop_map(|query| Route::Home { query }),
+                Just(Route::Login { o_return_to: None }),
+                Just(Route::Register),
+                Just(Route::Settings),
+                option::of(slug()).prop_map(|o_slug| Route::Editor { o_slug }),
+                slug().prop_map(|slug| Route::Article { slug }),
+                (username(), any::<bool>(), option::of(1..1000u32)).prop_map(
+                    |(username, is_favorites, page)| Route::Profile {
+                        username,
+                        is_favorites,
+                        query: FeedQuery {
+                            tab: None,
+                            tag: None,
+                            page,
+                        },
+                    }
+                ),
+            ]
+        }
+
+        proptest! {
+            #[test]
+            fn routes_round_trip_through_their_hash(route in route()) {
+                let url = format!("https://localhost:8080/#{}", route.as_hash());
+                prop_assert_eq!(Route::try_from(url), Ok(route.clone()));
+            }
+
+            #[test]
+            fn return_to_routes_round_trip(route in route()) {
+                let login = Route::Login { o_return_to: Some(route.as_hash()) };
+                prop_assert_eq!(Route::from_path(&login.as_hash()), login);
+            }
+        }
     }
 }
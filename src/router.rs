@@ -0,0 +1,204 @@
+//! Keeping the current route in the browser's URL.
+//!
+//! Routes either live in the URL's hash (`/#/article/slug`), which works with any static
+//! file server, or in the URL's path (`/article/slug`) using the History API, which needs
+//! a server that serves `index.html` for unknown paths. The mode is chosen at startup
+//! with a `<meta name="conduit-routing" content="history">` tag, alongside a
+//! `<base href="/">` so the page's relative asset paths work on any route.
+use mogwai::prelude::*;
+use std::cell::Cell;
+use wasm_bindgen::{JsValue, UnwrapThrowExt};
+use web_sys::{Element, MouseEvent, PopStateEvent};
+
+use crate::route::Route;
+
+/// How routes are kept in the browser's URL.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RoutingMode {
+    /// Routes live in the URL's hash, eg. `/#/article/slug`.
+    Hash,
+    /// Routes live in the URL's path, eg. `/article/slug`.
+    History,
+}
+
+thread_local! {
+    static MODE: Cell<RoutingMode> = Cell::new(RoutingMode::Hash);
+}
+
+impl RoutingMode {
+    /// Read the routing mode from the document's `conduit-routing` meta tag, defaulting
+    /// to hash routing.
+    pub fn detect() -> Self {
+        let o_content = utils::document()
+            .query_selector("meta[name=conduit-routing]")
+            .ok()
+            .flatten()
+            .and_then(|meta| meta.get_attribute("content"));
+        match o_content.as_deref() {
+            Some("history") => RoutingMode::History,
+            _ => RoutingMode::Hash,
+        }
+    }
+
+    /// The routing mode the app was started with.
+    pub fn current() -> Self {
+        MODE.with(|mode| mode.get())
+    }
+}
+
+/// Start routing in the given mode.
+///
+/// In history mode a hash URL, eg. an old shared link, is rewritten to its clean
+/// equivalent.
+pub fn init(mode: RoutingMode) {
+    MODE.with(|current| current.set(mode));
+    if mode == RoutingMode::History {
+        let window = utils::window();
+        let hash = window.location().hash().unwrap_or_default();
+        if hash.starts_with("#/") {
+            if let Ok(history) = window.history() {
+                let _ = history.replace_state_with_url(&JsValue::NULL, "", Some(&hash[1..]));
+            }
+        }
+    }
+}
+
+/// The route of the browser's current URL.
+pub fn current_route() -> Route {
+    let location = utils::window().location();
+    match RoutingMode::current() {
+        // no hash at all means we're at the root
+        RoutingMode::Hash => Route::try_from(location.href().unwrap_throw())
+            .unwrap_or_else(|_| Route::from_path("/")),
+        RoutingMode::History => Route::from_path(&format!(
+            "{}{}",
+            location.pathname().unwrap_throw(),
+            location.search().unwrap_throw()
+        )),
+    }
+}
+
+/// Navigate to the given route, adding an entry to the browser's history.
+pub fn navigate(route: &Route) {
+    let window = utils::window();
+    match RoutingMode::current() {
+        RoutingMode::Hash => {
+            let _ = window.location().set_hash(&route.as_hash());
+        }
+        RoutingMode::History => {
+            if let Ok(history) = window.history() {
+                let _ = history.push_state_with_url(&JsValue::NULL, "", Some(&route.as_hash()));
+            }
+            // pushState doesn't fire popstate, so we fire it ourselves to let the nav
+            // know the route changed
+            if let Ok(event) = PopStateEvent::new("popstate") {
+                let _ = window.dispatch_event(&event);
+            }
+        }
+    }
+}
+
+/// Navigate to the given route in place of the current one, so the back button skips
+/// the current route. Used by route guards.
+pub fn redirect(route: &Route) {
+    let window = utils::window();
+    match RoutingMode::current() {
+        RoutingMode::Hash => {
+            let _ = window.location().replace(&format!("#{}", route.as_hash()));
+        }
+        RoutingMode::History => {
+            if let Ok(history) = window.history() {
+                let _ = history.replace_state_with_url(&JsValue::NULL, "", Some(&route.as_hash()));
+            }
+            if let Ok(event) = PopStateEvent::new("popstate") {
+                let _ = window.dispatch_event(&event);
+            }
+        }
+    }
+}
+
+/// In history mode, find the route of an in-app link that was clicked so we can
+/// navigate to it without reloading the page. If one is found the click's default
+/// action is prevented.
+///
+/// Returns `None` in hash mode and for clicks that should be left to the browser -
+/// modified clicks, links that open elsewhere and links to other sites.
+pub fn intercept_link(ev: &Event) -> Option<Route> {
+    if RoutingMode::current() != RoutingMode::History {
+        return None;
+    }
+    let click = ev.dyn_ref::<MouseEvent>()?;
+    if click.default_prevented()
+        || click.button() != 0
+        || click.ctrl_key()
+        || click.meta_key()
+        || click.shift_key()
+        || click.alt_key()
+    {
+        return None;
+    }
+    let anchor = event_link(ev)?;
+    if anchor
+        .get_attribute("target")
+        .map(|target| target != "_self")
+        .unwrap_or(false)
+    {
+        return None;
+    }
+    let route = link_route(&anchor)?;
+    ev.prevent_default();
+    Some(route)
+}
+
+/// The link an event happened in, if any.
+pub fn event_link(ev: &Event) -> Option<Element> {
+    ev.target()?
+        .dyn_into::<Element>()
+        .ok()?
+        .closest("a[href]")
+        .ok()?
+}
+
+/// The route a link points to, if it's an in-app link to a page we have.
+///
+/// Same-origin paths we don't serve, eg. static files or API downloads, are left to the
+/// browser.
+pub fn link_route(anchor: &Element) -> Option<Route> {
+    if anchor.has_attribute("download") {
+        return None;
+    }
+    let href = anchor.get_attribute("href")?;
+    match Route::from_path(link_path(&href)?) {
+        Route::NotFound { .. } => None,
+        route => Some(route),
+    }
+}
+
+/// The in-app path a link's href points to, if any.
+fn link_path(href: &str) -> Option<&str> {
+    if href.starts_with('#') {
+        // our own hash links, eg. `#/login`, as well as the bare `#`
+        match &href[1..] {
+            "" => Some("/"),
+            path => Some(path),
+        }
+    } else if href.starts_with('/') && !href.starts_with("//") {
+        Some(href)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod router_tests {
+    use super::*;
+
+    #[test]
+    fn can_find_in_app_link_paths() {
+        assert_eq!(link_path("#/login"), Some("/login"));
+        assert_eq!(link_path("#"), Some("/"));
+        assert_eq!(link_path("/article/slug"), Some("/article/slug"));
+        assert_eq!(link_path("//example.com/article/slug"), None);
+        assert_eq!(link_path("https://thinkster.io"), None);
+    }
+}
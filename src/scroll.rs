@@ -0,0 +1,162 @@
+//! Scroll position and focus across page changes.
+//!
+//! Each history entry is tagged with a key in its `history.state` and the page's scroll
+//! position is remembered under that key as it scrolls. When a new page is shown,
+//! going back or forward to a tagged entry restores its position, while any other
+//! navigation starts at the top. Either way focus moves to the page's heading so
+//! screen readers announce the new page.
+//!
+//! A page that's still loading may be too short to scroll back to where it was left, so
+//! restoring keeps trying for a while, and positions aren't saved until it's done.
+use mogwai::prelude::*;
+use std::{cell::RefCell, collections::HashMap};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{FocusOptions, History, ScrollRestoration};
+
+use crate::timer;
+
+/// The page's main heading - the first heading after the nav.
+const HEADING_SELECTOR: &str =
+    "nav.navbar + * h1, nav.navbar + * h2, nav.navbar + * h3, nav.navbar + * h4";
+
+/// How long to wait between attempts to restore a position.
+const RESTORE_INTERVAL: i32 = 100;
+
+/// How many times to try restoring a position before leaving the page where it is.
+const RESTORE_ATTEMPTS: u32 = 30;
+
+thread_local! {
+    /// Scroll positions by history entry key.
+    static POSITIONS: RefCell<HashMap<u64, f64>> = RefCell::new(HashMap::new());
+    /// The history entry key and position being restored, if any.
+    static RESTORING: RefCell<Option<(u64, f64)>> = RefCell::new(None);
+}
+
+fn history() -> Option<History> {
+    utils::window().history().ok()
+}
+
+/// The key of the current history entry, if it has been given one.
+fn entry_key(history: &History) -> Option<u64> {
+    history.state().ok()?.as_f64().map(|key| key as u64)
+}
+
+/// Give the current history entry a new key. Keys are timestamps, so they stay unique
+/// across page reloads.
+fn tag_entry(history: &History) -> u64 {
+    let key = js_sys::Date::now() as u64;
+    let _ = history.replace_state(&JsValue::from_f64(key as f64), "");
+    key
+}
+
+/// Take over scroll restoration from the browser.
+pub fn init() {
+    if let Some(history) = history() {
+        let _ = history.set_scroll_restoration(ScrollRestoration::Manual);
+        if entry_key(&history).is_none() {
+            tag_entry(&history);
+        }
+    }
+}
+
+/// Whether the current history entry has been shown before, i.e. we got here by going
+/// back or forward. Only meaningful until the entry's page is shown.
+pub fn is_returning() -> bool {
+    history().as_ref().and_then(entry_key).is_some()
+}
+
+/// Remember the current scroll position of the current history entry.
+///
+/// Does nothing while a position is being restored, as scrolling then is only our own
+/// attempts.
+pub fn save() {
+    if RESTORING.with(|restoring| restoring.borrow().is_some()) {
+        return;
+    }
+    let o_key = history().as_ref().and_then(entry_key);
+    if let (Some(key), Ok(y)) = (o_key, utils::window().scroll_y()) {
+        POSITIONS.with(|positions| positions.borrow_mut().insert(key, y));
+    }
+}
+
+/// Scroll and focus a page that was just shown.
+///
+/// Entries we've seen before are scrolled back to where they were left, new ones to
+/// the top.
+pub fn page_shown() {
+    focus_heading();
+    let o_restore = history().and_then(|history| match entry_key(&history) {
+        Some(key) => POSITIONS.with(|positions| positions.borrow().get(&key).map(|y| (key, *y))),
+        None => {
+            tag_entry(&history);
+            None
+        }
+    });
+    RESTORING.with(|restoring| *restoring.borrow_mut() = o_restore);
+    match o_restore {
+        Some(restore) => {
+            if !try_restore(restore) {
+                spawn_local(keep_restoring(restore));
+            }
+        }
+        None => utils::window().scroll_to_with_x_and_y(0.0, 0.0),
+    }
+}
+
+/// Scroll to a position being restored, returning whether it was reached.
+fn try_restore((key, y): (u64, f64)) -> bool {
+    let window = utils::window();
+    window.scroll_to_with_x_and_y(0.0, y);
+    let is_reached = window
+        .scroll_y()
+        .map(|scroll_y| (scroll_y - y).abs() < 1.0)
+        .unwrap_or(true);
+    if is_reached {
+        stop_restoring(key);
+    }
+    is_reached
+}
+
+/// Keep trying to restore a position as the page loads, until it's reached, another
+/// page is shown, or we give up.
+async fn keep_restoring(restore: (u64, f64)) {
+    for _ in 0..RESTORE_ATTEMPTS {
+        timer::sleep(RESTORE_INTERVAL).await;
+        if RESTORING.with(|restoring| *restoring.borrow() != Some(restore)) {
+            return;
+        }
+        if try_restore(restore) {
+            return;
+        }
+    }
+    stop_restoring(restore.0);
+}
+
+/// Start saving positions again, unless another entry's position is being restored.
+fn stop_restoring(key: u64) {
+    RESTORING.with(|restoring| {
+        let mut restoring = restoring.borrow_mut();
+        if restoring.map(|(restoring_key, _)| restoring_key) == Some(key) {
+            *restoring = None;
+        }
+    });
+}
+
+/// Move focus to the page's heading without scrolling to it.
+fn focus_heading() {
+    let o_heading = utils::document()
+        .query_selector(HEADING_SELECTOR)
+        .ok()
+        .flatten()
+        .and_then(|heading| heading.dyn_into::<HtmlElement>().ok());
+    if let Some(heading) = o_heading {
+        // headings can't take focus unless we ask
+        if !heading.has_attribute("tabindex") {
+            let _ = heading.set_attribute("tabindex", "-1");
+        }
+        let mut options = FocusOptions::new();
+        options.prevent_scroll(true);
+        let _ = heading.focus_with_options(&options);
+    }
+}
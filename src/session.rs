@@ -0,0 +1,84 @@
+//! The signed-in user, shared by the whole app.
+//!
+//! `App` owns the session and hands clones of it to the nav and each page. Components
+//! subscribe to its changes instead of reading the store themselves, so signing in, out
+//! or updating settings is reflected everywhere at once.
+use mogwai::prelude::*;
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{api::User, store};
+
+/// A handle to the app-wide session. Clones share the same session.
+#[derive(Clone)]
+pub struct Session {
+    o_user: Rc<RefCell<Option<User>>>,
+    tx_change: Transmitter<Option<User>>,
+}
+
+impl Session {
+    /// Load the session from the store.
+    pub fn load() -> Self {
+        Session {
+            o_user: Rc::new(RefCell::new(store::read_user().ok())),
+            tx_change: Transmitter::new(),
+        }
+    }
+
+    /// The signed-in user, if any.
+    pub fn user(&self) -> Option<User> {
+        self.o_user.borrow().clone()
+    }
+
+    /// The signed-in user's auth token, if any.
+    pub fn token(&self) -> Option<String> {
+        self.o_user.borrow().as_ref().map(|user| user.token.clone())
+    }
+
+    /// Whether the signed-in user has the given username.
+    pub fn is_user(&self, username: &str) -> bool {
+        self.o_user
+            .borrow()
+            .as_ref()
+            .map(|user| user.username == username)
+            .unwrap_or(false)
+    }
+
+    /// A receiver of every change to the signed-in user.
+    pub fn changes(&self) -> Receiver<Option<User>> {
+        self.tx_change.spawn_recv()
+    }
+
+    /// Sign in the given user, remembering them across browser sessions or not.
+    pub fn sign_in(&self, user: &User, remember: bool) -> Result<(), store::Error> {
+        store::write_user_remembered(user, remember)?;
+        self.set(Some(user.clone()));
+        Ok(())
+    }
+
+    /// Update the signed-in user's details, eg. after saving their settings.
+    pub fn update_user(&self, user: &User) -> Result<(), store::Error> {
+        store::write_user(user)?;
+        self.set(Some(user.clone()));
+        Ok(())
+    }
+
+    /// Sign out the current user.
+    pub fn sign_out(&self) -> Result<(), store::Error> {
+        let result = store::delete_user();
+        self.set(None);
+        result
+    }
+
+    /// Re-read the session from the store, eg. after another tab changed it.
+    pub fn reload(&self) {
+        self.set(store::read_user().ok());
+    }
+
+    fn set(&self, o_user: Option<User>) {
+        if *self.o_user.borrow() == o_user {
+            return;
+        }
+        *self.o_user.borrow_mut() = o_user.clone();
+        self.tx_change.send(&o_user);
+    }
+}
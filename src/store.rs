@@ -1,72 +1,433 @@
-use serde::{de::DeserializeOwned, Serialize};
-use serde_json;
+//! Persisting application state in the browser's storage.
+//!
+//! Items are kept in a [`Backend`] - usually the browser's local storage, falling back
+//! to memory when the browser blocks storage access.
+//!
+//! Every item is written inside an [`Envelope`] that records the schema version it was
+//! written with, under a key namespaced by that version (eg. `conduit:v2:user`). When
+//! an item is read from an older version it is run through the migration chain, written
+//! back under the current key and the old key is removed. Version 1 is the original,
+//! unversioned format: raw JSON stored under the bare item name.
+use log::warn;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{self, Value};
 use snafu::{OptionExt, ResultExt, Snafu};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 use web_sys::Storage;
 
 use crate::api::User;
 
+/// The schema version items are written with.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// A migration from one schema version's data to the next.
+type Migration = fn(Value) -> Result<Value, Error>;
+
+/// The migration chain. The migration at index `n` moves data from version `n + 1` to
+/// version `n + 2`, so there must always be `SCHEMA_VERSION - 1` entries.
+const MIGRATIONS: [Migration; (SCHEMA_VERSION - 1) as usize] = [
+    // v1 -> v2: the data is unchanged, it only gains an envelope and a namespaced key.
+    |data| Ok(data),
+];
+
 /// An enumeration of all storage errors.
 #[derive(Debug, Snafu)]
 pub enum Error {
+    #[snafu(display("the browser doesn't allow storage here"))]
     SecurityViolation,
+    #[snafu(display("couldn't encode the item: {}", source))]
     CantSerialize { source: serde_json::Error },
+    #[snafu(display("couldn't read the stored item: {}", source))]
     CantDeserialize { source: serde_json::Error },
+    #[snafu(display("local storage is unavailable"))]
     NoLocalStorage,
+    #[snafu(display("session storage is unavailable"))]
+    NoSessionStorage,
+    #[snafu(display("couldn't store the item, storage may be full"))]
     CantStore,
+    #[snafu(display("couldn't access storage"))]
     CantGetKey,
+    #[snafu(display("nothing is stored"))]
     NoSuchKey,
+    #[snafu(display("unsupported schema version {}", version))]
+    UnsupportedVersion { version: u32 },
+}
+
+/// A stored item along with the schema version it was written with.
+#[derive(Debug, Deserialize, Serialize)]
+struct Envelope<T> {
+    version: u32,
+    data: T,
+}
+
+/// The storage key of the item with the given name at the given schema version.
+fn versioned_key(version: u32, name: &str) -> String {
+    if version <= 1 {
+        name.to_string()
+    } else {
+        format!("conduit:v{}:{}", version, name)
+    }
+}
+
+/// Run the given data through the migration chain, from `version` up to
+/// `SCHEMA_VERSION`.
+fn migrate(version: u32, data: Value) -> Result<Value, Error> {
+    if version == 0 || version > SCHEMA_VERSION {
+        return UnsupportedVersion { version }.fail();
+    }
+    MIGRATIONS[(version - 1) as usize..]
+        .iter()
+        .try_fold(data, |data, migration| migration(data))
 }
 
-fn write_item<T: Serialize>(key: &str, item: &T) -> Result<(), Error> {
-    let str_value = serde_json::to_string(item).with_context(|| CantSerialize)?;
-    let storage: Storage = mogwai::utils::window()
-        .local_storage()
-        .ok()
-        .with_context(|| NoLocalStorage)?
-        .with_context(|| SecurityViolation)?;
-    storage
-        .set_item(key, &str_value)
-        .ok()
-        .with_context(|| CantStore)
+/// Decode a stored string written with the given schema version.
+fn decode_envelope(version: u32, item_str: &str) -> Result<Envelope<Value>, Error> {
+    if version <= 1 {
+        let data = serde_json::from_str(item_str).with_context(|| CantDeserialize)?;
+        Ok(Envelope { version, data })
+    } else {
+        serde_json::from_str(item_str).with_context(|| CantDeserialize)
+    }
+}
+
+/// A key/value store that items can be kept in.
+pub trait Backend {
+    fn get_item(&self, key: &str) -> Result<Option<String>, Error>;
+
+    fn set_item(&self, key: &str, value: &str) -> Result<(), Error>;
+
+    fn remove_item(&self, key: &str) -> Result<(), Error>;
+}
+
+/// One of the browser's web storage areas.
+pub struct WebStorage {
+    storage: Storage,
+}
+
+impl WebStorage {
+    /// The browser's local storage, which persists across browser sessions.
+    pub fn local() -> Result<Self, Error> {
+        let storage = mogwai::utils::window()
+            .local_storage()
+            .ok()
+            .with_context(|| NoLocalStorage)?
+            .with_context(|| SecurityViolation)?;
+        Ok(WebStorage { storage })
+    }
+
+    /// The browser's session storage, which is cleared when the tab is closed.
+    pub fn session() -> Result<Self, Error> {
+        let storage = mogwai::utils::window()
+            .session_storage()
+            .ok()
+            .with_context(|| NoSessionStorage)?
+            .with_context(|| SecurityViolation)?;
+        Ok(WebStorage { storage })
+    }
 }
 
-fn read_item<T: DeserializeOwned>(key: &str) -> Result<T, Error> {
-    let storage: Storage = mogwai::utils::window()
-        .local_storage()
-        .ok()
-        .with_context(|| NoLocalStorage)?
-        .with_context(|| SecurityViolation)?;
+impl Backend for WebStorage {
+    fn get_item(&self, key: &str) -> Result<Option<String>, Error> {
+        self.storage.get_item(key).ok().with_context(|| CantGetKey)
+    }
 
-    let item_str: String = storage
-        .get_item(key)
-        .ok()
-        .with_context(|| CantGetKey)?
-        .with_context(|| NoSuchKey)?;
+    fn set_item(&self, key: &str, value: &str) -> Result<(), Error> {
+        self.storage
+            .set_item(key, value)
+            .ok()
+            .with_context(|| CantStore)
+    }
 
-    serde_json::from_str(&item_str).with_context(|| CantDeserialize)
+    fn remove_item(&self, key: &str) -> Result<(), Error> {
+        self.storage
+            .remove_item(key)
+            .ok()
+            .with_context(|| CantGetKey)
+    }
 }
 
-fn remove_item(key: &str) -> Result<(),Error> {
-    let storage: Storage = mogwai::utils::window()
-        .local_storage()
-        .ok()
-        .with_context(|| NoLocalStorage)?
-        .with_context(|| SecurityViolation)?;
+/// Items kept in memory. Used in tests and when the browser blocks storage.
+///
+/// Clones share the same items.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStorage {
+    items: Rc<RefCell<HashMap<String, String>>>,
+}
+
+impl Backend for MemoryStorage {
+    fn get_item(&self, key: &str) -> Result<Option<String>, Error> {
+        Ok(self.items.borrow().get(key).cloned())
+    }
+
+    fn set_item(&self, key: &str, value: &str) -> Result<(), Error> {
+        self.items
+            .borrow_mut()
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn remove_item(&self, key: &str) -> Result<(), Error> {
+        self.items.borrow_mut().remove(key);
+        Ok(())
+    }
+}
+
+thread_local! {
+    static PERSISTENT_FALLBACK: MemoryStorage = MemoryStorage::default();
+    static SESSION_FALLBACK: MemoryStorage = MemoryStorage::default();
+}
+
+/// The backend used to persist items - local storage if the browser allows it,
+/// otherwise an in-memory store that lasts as long as the page.
+pub fn persistent() -> Box<dyn Backend> {
+    match WebStorage::local() {
+        Ok(storage) => Box::new(storage),
+        Err(err) => {
+            warn!(
+                "local storage is unavailable ({}), keeping items in memory",
+                err
+            );
+            Box::new(PERSISTENT_FALLBACK.with(|memory| memory.clone()))
+        }
+    }
+}
+
+/// The backend used for items that should be forgotten when the tab is closed -
+/// session storage if the browser allows it, otherwise an in-memory store that
+/// lasts as long as the page.
+pub fn session() -> Box<dyn Backend> {
+    match WebStorage::session() {
+        Ok(storage) => Box::new(storage),
+        Err(err) => {
+            warn!(
+                "session storage is unavailable ({}), keeping items in memory",
+                err
+            );
+            Box::new(SESSION_FALLBACK.with(|memory| memory.clone()))
+        }
+    }
+}
+
+fn encode_envelope<T: Serialize>(data: &T) -> Result<String, Error> {
+    let envelope = Envelope {
+        version: SCHEMA_VERSION,
+        data,
+    };
+    serde_json::to_string(&envelope).with_context(|| CantSerialize)
+}
+
+fn write_item<T: Serialize>(backend: &dyn Backend, name: &str, item: &T) -> Result<(), Error> {
+    let str_value = encode_envelope(item)?;
+    backend.set_item(&versioned_key(SCHEMA_VERSION, name), &str_value)
+}
+
+fn read_item<T: DeserializeOwned>(backend: &dyn Backend, name: &str) -> Result<T, Error> {
+    let current_key = versioned_key(SCHEMA_VERSION, name);
+
+    for version in (1..=SCHEMA_VERSION).rev() {
+        let key = versioned_key(version, name);
+        if let Some(item_str) = backend.get_item(&key)? {
+            let Envelope { version, data } = decode_envelope(version, &item_str)?;
+            let data = migrate(version, data)?;
+            if key != current_key {
+                // Move the item to the current key so we only migrate once. Failing
+                // to do so is not fatal - we'll simply migrate again next time.
+                let upgraded = encode_envelope(&data)
+                    .and_then(|str_value| backend.set_item(&current_key, &str_value));
+                if upgraded.is_ok() {
+                    let _ = backend.remove_item(&key);
+                }
+            }
+            return serde_json::from_value(data).with_context(|| CantDeserialize);
+        }
+    }
 
-    storage
-        .remove_item(key)
-        .ok()
-        .with_context(|| CantGetKey)
+    NoSuchKey.fail()
 }
 
+fn remove_item(backend: &dyn Backend, name: &str) -> Result<(), Error> {
+    for version in 1..=SCHEMA_VERSION {
+        backend.remove_item(&versioned_key(version, name))?;
+    }
+    Ok(())
+}
+
+pub fn write_user_to(backend: &dyn Backend, user: &User) -> Result<(), Error> {
+    write_item(backend, "user", user)
+}
+
+pub fn read_user_from(backend: &dyn Backend) -> Result<User, Error> {
+    read_item(backend, "user")
+}
+
+pub fn delete_user_from(backend: &dyn Backend) -> Result<(), Error> {
+    remove_item(backend, "user")
+}
+
+/// Whether the given storage key holds the signed-in user, at any schema version.
+pub fn is_user_key(key: &str) -> bool {
+    (1..=SCHEMA_VERSION).any(|version| versioned_key(version, "user") == key)
+}
+
+/// Write the user to the persistent backend if they should be remembered, otherwise
+/// to the session backend. The user is removed from the other backend.
+fn write_user_in(
+    persistent: &dyn Backend,
+    session: &dyn Backend,
+    user: &User,
+    remember: bool,
+) -> Result<(), Error> {
+    let (keep, forget) = if remember {
+        (persistent, session)
+    } else {
+        (session, persistent)
+    };
+    write_user_to(keep, user)?;
+    delete_user_from(forget)
+}
+
+/// Read the user, preferring a session-only user over a remembered one.
+fn read_user_in(persistent: &dyn Backend, session: &dyn Backend) -> Result<User, Error> {
+    read_user_from(session).or_else(|_| read_user_from(persistent))
+}
+
+/// Write the signed-in user, choosing whether they should be remembered across
+/// browser sessions.
+pub fn write_user_remembered(user: &User, remember: bool) -> Result<(), Error> {
+    write_user_in(persistent().as_ref(), session().as_ref(), user, remember)
+}
+
+/// Write the signed-in user back wherever they are currently kept.
 pub fn write_user(user: &User) -> Result<(), Error> {
-    write_item("user", user)
+    let session = session();
+    let remember = read_user_from(session.as_ref()).is_err();
+    write_user_in(persistent().as_ref(), session.as_ref(), user, remember)
 }
 
 pub fn read_user() -> Result<User, Error> {
-    read_item("user")
+    read_user_in(persistent().as_ref(), session().as_ref())
 }
 
 pub fn delete_user() -> Result<(), Error> {
-    remove_item("user")
+    delete_user_from(session().as_ref())?;
+    delete_user_from(persistent().as_ref())
+}
+
+#[cfg(test)]
+mod store_tests {
+    use super::*;
+    use serde_json::json;
+
+    fn jake() -> Value {
+        json!({
+            "email": "jake@jake.jake",
+            "token": "jwt.token.here",
+            "username": "jake",
+            "bio": null,
+            "image": null
+        })
+    }
+
+    #[test]
+    fn keys_are_namespaced_by_version() {
+        assert_eq!(versioned_key(1, "user"), "user");
+        assert_eq!(versioned_key(2, "user"), "conduit:v2:user");
+    }
+
+    #[test]
+    fn can_recognize_user_keys() {
+        assert!(is_user_key("user"));
+        assert!(is_user_key("conduit:v2:user"));
+        assert!(!is_user_key("conduit:v2:username"));
+    }
+
+    #[test]
+    fn can_migrate_legacy_user() {
+        let envelope = decode_envelope(1, &jake().to_string()).unwrap();
+        assert_eq!(envelope.version, 1);
+        let data = migrate(envelope.version, envelope.data).unwrap();
+        let user: User = serde_json::from_value(data).unwrap();
+        assert_eq!(user.username, "jake");
+    }
+
+    #[test]
+    fn can_round_trip_current_envelope() {
+        let user: User = serde_json::from_value(jake()).unwrap();
+        let str_value = encode_envelope(&user).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&str_value).unwrap(),
+            json!({ "version": SCHEMA_VERSION, "data": jake() })
+        );
+        let Envelope { version, data } = decode_envelope(SCHEMA_VERSION, &str_value).unwrap();
+        assert_eq!(migrate(version, data).unwrap(), jake());
+    }
+
+    #[test]
+    fn can_write_read_and_delete_user() {
+        let memory = MemoryStorage::default();
+        let user: User = serde_json::from_value(jake()).unwrap();
+        assert!(matches!(read_user_from(&memory), Err(Error::NoSuchKey)));
+
+        write_user_to(&memory, &user).unwrap();
+        assert_eq!(read_user_from(&memory).unwrap(), user);
+
+        delete_user_from(&memory).unwrap();
+        assert!(matches!(read_user_from(&memory), Err(Error::NoSuchKey)));
+    }
+
+    #[test]
+    fn reading_legacy_user_upgrades_it_in_place() {
+        let memory = MemoryStorage::default();
+        memory.set_item("user", &jake().to_string()).unwrap();
+
+        let user = read_user_from(&memory).unwrap();
+        assert_eq!(user.username, "jake");
+        assert_eq!(memory.get_item("user").unwrap(), None);
+        assert!(memory
+            .get_item(&versioned_key(SCHEMA_VERSION, "user"))
+            .unwrap()
+            .is_some());
+        assert_eq!(read_user_from(&memory).unwrap(), user);
+    }
+
+    #[test]
+    fn deleting_user_removes_every_version() {
+        let memory = MemoryStorage::default();
+        memory.set_item("user", &jake().to_string()).unwrap();
+        delete_user_from(&memory).unwrap();
+        assert!(matches!(read_user_from(&memory), Err(Error::NoSuchKey)));
+    }
+
+    #[test]
+    fn forgotten_users_only_live_in_the_session() {
+        let persistent = MemoryStorage::default();
+        let session = MemoryStorage::default();
+        let user: User = serde_json::from_value(jake()).unwrap();
+
+        write_user_in(&persistent, &session, &user, true).unwrap();
+        assert_eq!(read_user_from(&persistent).unwrap(), user);
+        assert!(read_user_from(&session).is_err());
+
+        write_user_in(&persistent, &session, &user, false).unwrap();
+        assert!(read_user_from(&persistent).is_err());
+        assert_eq!(read_user_in(&persistent, &session).unwrap(), user);
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        assert!(migrate(0, jake()).is_err());
+        assert!(migrate(SCHEMA_VERSION + 1, jake()).is_err());
+    }
+
+    #[test]
+    fn errors_read_as_messages() {
+        assert_eq!(
+            Error::NoLocalStorage.to_string(),
+            "local storage is unavailable"
+        );
+        assert_eq!(
+            format!("Could not forget you on this device: {}", Error::CantGetKey),
+            "Could not forget you on this device: couldn't access storage"
+        );
+    }
 }
@@ -0,0 +1,11 @@
+//! Waiting in async code.
+use mogwai::prelude::*;
+
+/// Resolves after the given number of milliseconds.
+pub async fn sleep(millis: i32) {
+    let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+        let _ =
+            utils::window().set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, millis);
+    });
+    let _ = JsFuture::from(promise).await;
+}
@@ -0,0 +1,102 @@
+//! The document's title, as shown in browser tabs and history.
+//!
+//! The nav gives each route a generic title as it's navigated to. Pages that load data
+//! can then replace it with something more specific, eg. an article's title, through
+//! the `Title` handle they take when they're built. Handles of pages that have since
+//! been navigated away from do nothing, so a slow response can't retitle the wrong page,
+//! until the page is shown again.
+use mogwai::utils;
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use crate::route::Route;
+
+/// Appended to every page's title.
+const APP_NAME: &str = "Conduit";
+
+thread_local! {
+    /// Counts navigations, to tell current handles from stale ones.
+    static GENERATION: Cell<u32> = Cell::new(0);
+}
+
+/// The document title of a page with the given name.
+pub fn format_title(page: &str) -> String {
+    format!("{} — {}", page, APP_NAME)
+}
+
+/// Give a route that was just navigated to its generic title. Handles taken before this
+/// stop working.
+pub fn navigated(route: &Route) {
+    GENERATION.with(|generation| generation.set(generation.get().wrapping_add(1)));
+    utils::document().set_title(&format_title(&route.as_title()));
+}
+
+fn current_generation() -> u32 {
+    GENERATION.with(|generation| generation.get())
+}
+
+/// A handle to the title of the page that was current when it was taken. Clones share
+/// the same title.
+#[derive(Clone, Debug)]
+pub struct Title {
+    generation: Rc<Cell<u32>>,
+    o_name: Rc<RefCell<Option<String>>>,
+}
+
+impl Title {
+    /// A handle to the current page's title.
+    pub fn current() -> Self {
+        Title {
+            generation: Rc::new(Cell::new(current_generation())),
+            o_name: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    /// Whether the page this handle was taken for is still the current page.
+    pub fn is_current(&self) -> bool {
+        self.generation.get() == current_generation()
+    }
+
+    /// Title the page with the given name, if it's still the current page.
+    pub fn set(&self, page: &str) {
+        *self.o_name.borrow_mut() = Some(page.to_string());
+        if self.is_current() {
+            utils::document().set_title(&format_title(page));
+        }
+    }
+
+    /// Make this the current page's title again, eg. when going back to the page.
+    pub fn restore(&self) {
+        self.generation.set(current_generation());
+        if let Some(name) = self.o_name.borrow().as_ref() {
+            utils::document().set_title(&format_title(name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod title_tests {
+    use super::*;
+
+    #[test]
+    fn titles_name_the_app() {
+        assert_eq!(
+            format_title("How to train your dragon"),
+            "How to train your dragon — Conduit"
+        );
+    }
+
+    #[test]
+    fn handles_go_stale_after_navigating() {
+        let title = Title::current();
+        assert!(title.is_current());
+        GENERATION.with(|generation| generation.set(generation.get().wrapping_add(1)));
+        assert!(!title.is_current());
+        assert!(Title::current().is_current());
+
+        title.restore();
+        assert!(title.is_current());
+    }
+}
@@ -1,5 +1,11 @@
 use mogwai::prelude::*;
-use web_sys::{HtmlInputElement, HtmlTextAreaElement};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+use web_sys::{Element, HtmlInputElement, HtmlTextAreaElement, KeyboardEvent};
+
+use crate::api;
 
 pub trait InputOrArea: IsDomNode {
     fn value(&self) -> String;
@@ -13,10 +19,90 @@ pub trait InputOrArea: IsDomNode {
     ) -> ViewBuilder<Self>;
 }
 
+/// A check on the value of a text input.
+#[derive(Clone)]
+pub enum Validator {
+    Required,
+    Email,
+    /// At least this many characters.
+    MinLength(usize),
+    /// At most this many characters.
+    MaxLength(usize),
+    /// An http or https URL.
+    Url,
+    /// The same as another input's value, named for the message.
+    Matches {
+        name: String,
+        value: Rc<RefCell<String>>,
+    },
+}
+
+impl Validator {
+    /// What's wrong with the given value, if anything, in the words the API uses.
+    ///
+    /// Only `Required` and `Matches` check empty values, so optional inputs can be left
+    /// blank.
+    pub fn check(&self, value: &str) -> Option<String> {
+        let is_valid = match self {
+            Validator::Required => !value.trim().is_empty(),
+            Validator::Matches { value: other, .. } => value == other.borrow().as_str(),
+            _ if value.is_empty() => true,
+            Validator::Email => is_email(value),
+            Validator::MinLength(min) => value.chars().count() >= *min,
+            Validator::MaxLength(max) => value.chars().count() <= *max,
+            Validator::Url => is_url(value),
+        };
+        if is_valid {
+            return None;
+        }
+        Some(match self {
+            Validator::Required => "can't be blank".to_string(),
+            Validator::Email | Validator::Url => "is invalid".to_string(),
+            Validator::MinLength(min) => format!("is too short (minimum is {} characters)", min),
+            Validator::MaxLength(max) => format!("is too long (maximum is {} characters)", max),
+            Validator::Matches { name, .. } => format!("doesn't match {}", name),
+        })
+    }
+}
+
+/// Whether the value looks like an email address. The server has the final say.
+fn is_email(value: &str) -> bool {
+    let mut parts = value.splitn(2, '@');
+    let (local, domain) = match (parts.next(), parts.next()) {
+        (Some(local), Some(domain)) => (local, domain),
+        _ => return false,
+    };
+    !local.is_empty()
+        && !domain.contains('@')
+        && domain.contains('.')
+        && domain.split('.').all(|label| !label.is_empty())
+        && !value.chars().any(char::is_whitespace)
+}
+
+/// Whether the value looks like an http or https URL with a host.
+fn is_url(value: &str) -> bool {
+    let rest = match value
+        .strip_prefix("https://")
+        .or_else(|| value.strip_prefix("http://"))
+    {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let host = rest.split(&['/', '?', '#'][..]).next();
+    host.map(|host| !host.is_empty()).unwrap_or(false) && !value.chars().any(char::is_whitespace)
+}
+
 pub struct TextInput<T> {
     o_input: Option<T>,
     pub value: String,
     pub placeholder: String,
+    /// The name of the value in API requests, used in its error messages.
+    pub name: String,
+    input_type: String,
+    validators: Vec<Validator>,
+    /// Whether the user has left the input. Errors are only shown after they have, so
+    /// they aren't told off halfway through typing.
+    is_touched: bool,
 }
 
 impl<T> TextInput<T> {
@@ -25,8 +111,179 @@ impl<T> TextInput<T> {
             o_input: None,
             value: value.to_string(),
             placeholder: placeholder.to_string(),
+            name: String::new(),
+            input_type: "text".to_string(),
+            validators: vec![],
+            is_touched: false,
+        }
+    }
+
+    /// Check the value with the given validators, naming it in their messages.
+    pub fn with_validators(mut self, name: &str, validators: Vec<Validator>) -> Self {
+        self.name = name.to_string();
+        self.validators = validators;
+        self
+    }
+
+    /// Use another type of input, eg. "password". Text areas ignore this.
+    pub fn with_type(mut self, input_type: &str) -> Self {
+        self.input_type = input_type.to_string();
+        self
+    }
+
+    /// What's wrong with the value, if anything.
+    pub fn error(&self) -> Option<String> {
+        self.validators
+            .iter()
+            .find_map(|validator| validator.check(&self.value))
+            .map(|message| format!("{} {}", self.name, message))
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.error().is_none()
+    }
+
+    fn validate(&self, tx_view: &Transmitter<TextInputOut>) {
+        let o_error = self.error();
+        tx_view.send(&TextInputOut::Validity {
+            name: self.name.clone(),
+            is_valid: o_error.is_none(),
+        });
+        if self.is_touched {
+            tx_view.send(&TextInputOut::Error(o_error));
+        }
+    }
+}
+
+/// A form group holding the input, highlighted with a message saying what's wrong when
+/// its value is.
+pub fn field_builder<T>(input: &Gizmo<TextInput<T>>) -> ViewBuilder<HtmlElement>
+where
+    T: IsDomNode + AsRef<Node> + InputOrArea,
+{
+    builder! {
+        <fieldset class=("form-group".to_string(), input.recv.branch_filter_map(|msg| msg.group_class()))>
+            {input.view_builder()}
+            <div class="form-control-feedback" aria-live="polite">
+                {(String::new(), input.recv.branch_filter_map(|msg| msg.error()))}
+            </div>
+        </fieldset>
+    }
+}
+
+/// Which of a form's inputs have invalid values, to know whether it can be submitted.
+#[derive(Default)]
+pub struct FormValidity {
+    invalid: Vec<String>,
+}
+
+impl FormValidity {
+    /// Start tracking an input from its initial value.
+    pub fn check<T>(&mut self, input: &TextInput<T>) {
+        self.set(&input.name, input.is_valid());
+    }
+
+    pub fn set(&mut self, name: &str, is_valid: bool) {
+        self.invalid.retain(|invalid| invalid != name);
+        if !is_valid {
+            self.invalid.push(name.to_string());
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.invalid.is_empty()
+    }
+}
+
+/// A form's submit button. It's disabled while the form's inputs are invalid, and shows
+/// a spinner and another label, eg. "Signing in…", while the form is being submitted.
+///
+/// Its owner keeps it in its state and updates the view by sending the
+/// `SubmitButtonMsg`s it returns through the owner's own view messages.
+pub struct SubmitButton {
+    label: String,
+    submitting_label: String,
+    is_submitting: bool,
+}
+
+impl SubmitButton {
+    pub fn new(label: &str, submitting_label: &str) -> Self {
+        SubmitButton {
+            label: label.to_string(),
+            submitting_label: submitting_label.to_string(),
+            is_submitting: false,
+        }
+    }
+
+    /// Whether a submission should go ahead. Enter can submit while the button is
+    /// disabled, and users double click.
+    pub fn can_submit(&self, validity: &FormValidity) -> bool {
+        !self.is_submitting && validity.is_valid()
+    }
+
+    pub fn set_submitting(&mut self, is_submitting: bool) -> SubmitButtonMsg {
+        self.is_submitting = is_submitting;
+        SubmitButtonMsg::Submitting(is_submitting)
+    }
+
+    /// The button, enabled at first if `is_valid`. The form's fieldset can disable its
+    /// inputs while submitting with `SubmitButtonMsg::is_submitting`.
+    pub fn builder(
+        &self,
+        is_valid: bool,
+        rx: Receiver<SubmitButtonMsg>,
+    ) -> ViewBuilder<HtmlElement> {
+        let label = self.label.clone();
+        let submitting_label = self.submitting_label.clone();
+        builder! {
+            <button
+                class="btn btn-lg btn-primary pull-xs-right"
+                type="submit"
+                boolean:disabled=(!is_valid, rx.branch_filter_map(|msg| msg.is_disabled()))>
+                <span class=("".to_string(), rx.branch_filter_map(|msg| msg.spinner_class()))>
+                </span>
+                {(
+                    &self.label,
+                    rx.branch_filter_map(move |msg| {
+                        msg.is_submitting().map(|is_submitting| {
+                            if is_submitting {
+                                submitting_label.clone()
+                            } else {
+                                label.clone()
+                            }
+                        })
+                    })
+                )}
+            </button>
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum SubmitButtonMsg {
+    CanSubmit(bool),
+    Submitting(bool),
+}
+
+impl SubmitButtonMsg {
+    fn is_disabled(&self) -> Option<bool> {
+        match self {
+            SubmitButtonMsg::CanSubmit(can_submit) => Some(!can_submit),
+            _ => None,
         }
     }
+
+    pub fn is_submitting(&self) -> Option<bool> {
+        match self {
+            SubmitButtonMsg::Submitting(is_submitting) => Some(*is_submitting),
+            _ => None,
+        }
+    }
+
+    fn spinner_class(&self) -> Option<String> {
+        self.is_submitting()
+            .map(|is_submitting| if is_submitting { "spinner" } else { "" }.to_string())
+    }
 }
 
 impl InputOrArea for HtmlInputElement {
@@ -47,9 +304,10 @@ impl InputOrArea for HtmlInputElement {
             <input
                 cast:type = Self
                 post:build = tx.contra_map(|input: &Self| TextInputIn::PostBuild(input.clone()))
-                on:keyup = tx.contra_map(|_| TextInputIn::UpdateValue)
-                class="form-control"
-                type="text"
+                on:input = tx.contra_map(|_| TextInputIn::UpdateValue)
+                on:blur = tx.contra_map(|_| TextInputIn::Touch)
+                class="form-control form-control-lg"
+                type=&text_input.input_type
                 placeholder=&text_input.placeholder />
         }
     }
@@ -73,7 +331,8 @@ impl InputOrArea for HtmlTextAreaElement {
             <textarea
                 cast:type = Self
                 post:build = tx.contra_map(|input: &Self| TextInputIn::PostBuild(input.clone()))
-                on:keyup = tx.contra_map(|_| TextInputIn::UpdateValue)
+                on:input = tx.contra_map(|_| TextInputIn::UpdateValue)
+                on:blur = tx.contra_map(|_| TextInputIn::Touch)
                 class="form-control form-control-lg"
                 rows="8"
                 placeholder=&text_area.placeholder >
@@ -87,11 +346,54 @@ pub enum TextInputIn<T> {
     PostBuild(T),
     SetValue(String),
     UpdateValue,
+    /// The user left the input.
+    Touch,
+    /// Check the value again, eg. after an input it has to match changed.
+    Validate,
+    /// Show what the server said is wrong with the value, if anything.
+    ServerErrors(Vec<String>),
 }
 
 #[derive(Clone)]
 pub enum TextInputOut {
     UpdatedValue(String),
+    /// The message to show about the value, if any.
+    Error(Option<String>),
+    Validity {
+        name: String,
+        is_valid: bool,
+    },
+}
+
+impl TextInputOut {
+    pub fn updated_value(&self) -> Option<String> {
+        match self {
+            TextInputOut::UpdatedValue(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    fn error(&self) -> Option<String> {
+        match self {
+            TextInputOut::Error(o_error) => Some(o_error.clone().unwrap_or_default()),
+            _ => None,
+        }
+    }
+
+    fn group_class(&self) -> Option<String> {
+        match self {
+            TextInputOut::Error(Some(_)) => Some("form-group has-danger".to_string()),
+            TextInputOut::Error(None) => Some("form-group".to_string()),
+            _ => None,
+        }
+    }
+
+    pub fn validity(&self) -> Option<(String, bool)> {
+        match self {
+            TextInputOut::Validity { name, is_valid } => Some((name.clone(), *is_valid)),
+            _ => None,
+        }
+    }
 }
 
 impl<T> Component for TextInput<T>
@@ -124,9 +426,23 @@ where
                     let old_value = std::mem::replace(&mut self.value, input.value());
                     if old_value != self.value {
                         tx_view.send(&TextInputOut::UpdatedValue(self.value.clone()));
+                        self.validate(tx_view);
                     }
                 }
             }
+            TextInputIn::Touch => {
+                self.is_touched = true;
+                self.validate(tx_view);
+            }
+            TextInputIn::Validate => self.validate(tx_view),
+            TextInputIn::ServerErrors(messages) => {
+                let o_error = if messages.is_empty() {
+                    self.error().filter(|_| self.is_touched)
+                } else {
+                    Some(format!("{} {}", self.name, messages.join(", ")))
+                };
+                tx_view.send(&TextInputOut::Error(o_error));
+            }
         }
     }
 
@@ -138,3 +454,574 @@ where
         T::view(self, tx, rx)
     }
 }
+
+/// The elements inside a dialog that can take focus.
+const FOCUSABLE: &str = "button, [href], input, select, textarea, [tabindex]:not([tabindex='-1'])";
+
+/// A modal dialog asking the user to confirm an action, eg. before doing something
+/// destructive.
+///
+/// Open it by sending `ConfirmIn::Open`. The user's answer comes back out of the gizmo
+/// as `ConfirmOut::Answered`. While it's open focus stays inside the dialog, and Escape
+/// or clicking outside it cancels.
+pub struct Confirm {
+    title: String,
+    message: String,
+    confirm_label: String,
+    o_dialog: Option<HtmlElement>,
+    /// Where focus was before opening, to put it back afterwards.
+    o_return_focus: Option<HtmlElement>,
+}
+
+impl Confirm {
+    pub fn new(title: &str, message: &str, confirm_label: &str) -> Self {
+        Confirm {
+            title: title.to_string(),
+            message: message.to_string(),
+            confirm_label: confirm_label.to_string(),
+            o_dialog: None,
+            o_return_focus: None,
+        }
+    }
+
+    fn focusable(&self) -> Vec<HtmlElement> {
+        let mut elements = vec![];
+        if let Some(Ok(nodes)) = self
+            .o_dialog
+            .as_ref()
+            .map(|d| d.query_selector_all(FOCUSABLE))
+        {
+            for index in 0..nodes.length() {
+                if let Some(element) = nodes.item(index).and_then(|node| node.dyn_into().ok()) {
+                    elements.push(element);
+                }
+            }
+        }
+        elements
+    }
+
+    /// Keep Tab and Shift+Tab cycling through the dialog's own elements.
+    fn trap_focus(&self, ev: &KeyboardEvent) {
+        let focusable = self.focusable();
+        let (first, last) = match (focusable.first(), focusable.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return,
+        };
+        let o_active = utils::document().active_element();
+        let is_active =
+            |element: &HtmlElement| o_active.as_ref() == Some(element.unchecked_ref::<Element>());
+        if ev.shift_key() && is_active(first) {
+            ev.prevent_default();
+            let _ = last.focus();
+        } else if !ev.shift_key() && is_active(last) {
+            ev.prevent_default();
+            let _ = first.focus();
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum ConfirmIn {
+    PostBuild(HtmlElement),
+    Open,
+    KeyDown(KeyboardEvent),
+    Answer(bool),
+}
+
+#[derive(Clone)]
+pub enum ConfirmOut {
+    IsOpen(bool),
+    /// The user confirmed (`true`) or cancelled (`false`).
+    Answered(bool),
+}
+
+impl ConfirmOut {
+    fn class(&self) -> Option<String> {
+        match self {
+            ConfirmOut::IsOpen(true) => Some("confirm open".to_string()),
+            ConfirmOut::IsOpen(false) => Some("confirm".to_string()),
+            _ => None,
+        }
+    }
+
+    /// The user's answer, if this is one.
+    pub fn answer(&self) -> Option<bool> {
+        match self {
+            ConfirmOut::Answered(is_confirmed) => Some(*is_confirmed),
+            _ => None,
+        }
+    }
+}
+
+impl Component for Confirm {
+    type ModelMsg = ConfirmIn;
+    type ViewMsg = ConfirmOut;
+    type DomNode = HtmlElement;
+
+    fn update(
+        &mut self,
+        msg: &ConfirmIn,
+        tx_view: &Transmitter<ConfirmOut>,
+        sub: &Subscriber<ConfirmIn>,
+    ) {
+        match msg {
+            ConfirmIn::PostBuild(dialog) => {
+                self.o_dialog = Some(dialog.clone());
+            }
+            ConfirmIn::Open => {
+                self.o_return_focus = utils::document()
+                    .active_element()
+                    .and_then(|element| element.dyn_into().ok());
+                tx_view.send(&ConfirmOut::IsOpen(true));
+                // Cancelling is the safe choice, so it gets focus.
+                if let Some(cancel) = self.focusable().first() {
+                    let _ = cancel.focus();
+                }
+            }
+            ConfirmIn::KeyDown(ev) => match ev.key().as_str() {
+                "Escape" => {
+                    ev.prevent_default();
+                    self.update(&ConfirmIn::Answer(false), tx_view, sub);
+                }
+                "Tab" => self.trap_focus(ev),
+                _ => {}
+            },
+            ConfirmIn::Answer(is_confirmed) => {
+                tx_view.send(&ConfirmOut::IsOpen(false));
+                if let Some(element) = self.o_return_focus.take() {
+                    let _ = element.focus();
+                }
+                tx_view.send(&ConfirmOut::Answered(*is_confirmed));
+            }
+        }
+    }
+
+    fn view(
+        &self,
+        tx: &Transmitter<ConfirmIn>,
+        rx: &Receiver<ConfirmOut>,
+    ) -> ViewBuilder<HtmlElement> {
+        builder! {
+            <div
+                class=("confirm".to_string(), rx.branch_filter_map(|msg| msg.class()))
+                post:build=tx.contra_map(|dialog: &HtmlElement| ConfirmIn::PostBuild(dialog.clone()))
+                on:click=tx.contra_filter_map(|ev: &Event| {
+                    // only clicks on the backdrop itself, not the dialog
+                    if ev.target() == ev.current_target() {
+                        Some(ConfirmIn::Answer(false))
+                    } else {
+                        None
+                    }
+                })
+                on:keydown=tx.contra_filter_map(|ev: &Event| {
+                    ev.dyn_ref::<KeyboardEvent>().cloned().map(ConfirmIn::KeyDown)
+                })>
+                <div class="confirm-dialog" role="alertdialog" aria-modal="true" aria-label=self.title.as_str()>
+                    <h4>{&self.title}</h4>
+                    <p>{&self.message}</p>
+                    <div class="confirm-actions">
+                        <button
+                            class="btn btn-outline-secondary"
+                            type="button"
+                            on:click=tx.contra_map(|_| ConfirmIn::Answer(false))>
+                            "Cancel"
+                        </button>
+                        <button
+                            class="btn btn-danger"
+                            type="button"
+                            on:click=tx.contra_map(|_| ConfirmIn::Answer(true))>
+                            {&self.confirm_label}
+                        </button>
+                    </div>
+                </div>
+            </div>
+        }
+    }
+}
+
+/// Add the tags in text typed into a tag input to the list, returning those that weren't
+/// in it already.
+///
+/// Commas separate tags, and tags are trimmed and lowercased so "Rust" and "rust " are
+/// the same tag.
+pub fn add_tags(tags: &mut Vec<String>, text: &str) -> Vec<String> {
+    let mut added = vec![];
+    for tag in text.split(',') {
+        let tag = tag.trim().to_lowercase();
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag.clone());
+            added.push(tag);
+        }
+    }
+    added
+}
+
+thread_local! {
+    /// The number of tag inputs made so far, to give each its own suggestions list.
+    static TAG_INPUTS: Cell<u32> = Cell::new(0);
+}
+
+/// An input for a list of tags, shown as removable chips.
+///
+/// Enter or a comma turns the text typed into a tag, and Backspace in the empty input
+/// removes the last one. Popular tags from the API are suggested while typing. Owners
+/// learn of changes to the tags through `TagInputOut::Tags`.
+pub struct TagInput {
+    /// The tags chosen, eg. for an article's `tagList`.
+    pub tags: Vec<String>,
+    /// Popular tags, to suggest those not chosen yet.
+    popular: Vec<String>,
+    /// The id of the suggestions' datalist, unique to this input.
+    suggestions_id: String,
+    o_input: Option<HtmlInputElement>,
+    tx_remove: Transmitter<String>,
+}
+
+impl TagInput {
+    pub fn new(tags: Vec<String>) -> Self {
+        let count = TAG_INPUTS.with(|count| {
+            count.set(count.get() + 1);
+            count.get()
+        });
+        let mut input = TagInput {
+            tags: vec![],
+            popular: vec![],
+            suggestions_id: format!("tag-suggestions-{}", count),
+            o_input: None,
+            tx_remove: Transmitter::new(),
+        };
+        add_tags(&mut input.tags, &tags.join(","));
+        input
+    }
+
+    fn suggestions(&self) -> Vec<String> {
+        self.popular
+            .iter()
+            .filter(|tag| !self.tags.contains(tag))
+            .cloned()
+            .collect()
+    }
+
+    fn chip_builder(&self, tag: &str) -> ViewBuilder<HtmlElement> {
+        let removed = tag.to_string();
+        builder! {
+            <span class="tag-default tag-pill">
+                <button
+                    class="tag-remove"
+                    type="button"
+                    aria-label=format!("Remove {}", tag)
+                    on:click=self.tx_remove.contra_map(move |_| removed.clone())>
+                    <i class="ion-close-round"></i>
+                </button>
+                {tag}
+            </span>
+        }
+    }
+
+    fn option_builder(tag: &str) -> ViewBuilder<HtmlElement> {
+        builder! {
+            <option value=tag></option>
+        }
+    }
+
+    fn suggest(&self, tx_view: &Transmitter<TagInputOut>) {
+        tx_view.send(&TagInputOut::Suggestions(Patch::RemoveAll));
+        for tag in self.suggestions().iter() {
+            tx_view.send(&TagInputOut::Suggestions(Patch::PushBack {
+                value: View::from(TagInput::option_builder(tag)),
+            }));
+        }
+    }
+
+    /// Turn the text typed so far into tags.
+    fn commit(&mut self, tx_view: &Transmitter<TagInputOut>) {
+        let input = match self.o_input.as_ref() {
+            Some(input) => input,
+            None => return,
+        };
+        let added = add_tags(&mut self.tags, &input.value());
+        input.set_value("");
+        if added.is_empty() {
+            return;
+        }
+        for tag in added.iter() {
+            tx_view.send(&TagInputOut::Chips(Patch::PushBack {
+                value: View::from(self.chip_builder(tag)),
+            }));
+        }
+        tx_view.send(&TagInputOut::Tags(self.tags.clone()));
+        self.suggest(tx_view);
+    }
+
+    fn remove(&mut self, tag: &str, tx_view: &Transmitter<TagInputOut>) {
+        if let Some(index) = self.tags.iter().position(|t| t == tag) {
+            self.tags.remove(index);
+            tx_view.send(&TagInputOut::Chips(Patch::Remove { index }));
+            tx_view.send(&TagInputOut::Tags(self.tags.clone()));
+            self.suggest(tx_view);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum TagInputIn {
+    PostBuild(HtmlInputElement),
+    KeyDown(KeyboardEvent),
+    /// The text changed, eg. by pasting a list of tags.
+    Input,
+    /// Turn the text typed so far into tags, eg. when the user leaves the input.
+    Commit,
+    Remove(String),
+    Popular(Vec<String>),
+}
+
+#[derive(Clone)]
+pub enum TagInputOut {
+    Chips(Patch<View<HtmlElement>>),
+    Suggestions(Patch<View<HtmlElement>>),
+    /// The tags chosen changed.
+    Tags(Vec<String>),
+}
+
+impl TagInputOut {
+    /// The tags chosen, if they changed.
+    pub fn tags(&self) -> Option<Vec<String>> {
+        match self {
+            TagInputOut::Tags(tags) => Some(tags.clone()),
+            _ => None,
+        }
+    }
+
+    fn chips(&self) -> Option<Patch<View<HtmlElement>>> {
+        match self {
+            TagInputOut::Chips(patch) => Some(patch.clone()),
+            _ => None,
+        }
+    }
+
+    fn suggestions(&self) -> Option<Patch<View<HtmlElement>>> {
+        match self {
+            TagInputOut::Suggestions(patch) => Some(patch.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl Component for TagInput {
+    type ModelMsg = TagInputIn;
+    type ViewMsg = TagInputOut;
+    type DomNode = HtmlElement;
+
+    fn bind(&self, sub: &Subscriber<TagInputIn>) {
+        sub.subscribe_map(&self.tx_remove.spawn_recv(), |tag| {
+            TagInputIn::Remove(tag.clone())
+        });
+        // Suggestions are a nicety, so there's nothing to do if they can't be had.
+        sub.send_async(async { TagInputIn::Popular(api::get_tags().await.unwrap_or_default()) });
+    }
+
+    fn update(
+        &mut self,
+        msg: &TagInputIn,
+        tx_view: &Transmitter<TagInputOut>,
+        _sub: &Subscriber<TagInputIn>,
+    ) {
+        match msg {
+            TagInputIn::PostBuild(input) => {
+                self.o_input = Some(input.clone());
+            }
+            TagInputIn::KeyDown(ev) => {
+                // Enter also picks from an input method's candidates.
+                if ev.is_composing() {
+                    return;
+                }
+                let is_empty = self
+                    .o_input
+                    .as_ref()
+                    .map(|input| input.value().is_empty())
+                    .unwrap_or(true);
+                match ev.key().as_str() {
+                    "Enter" | "," => {
+                        // Enter would submit the form, and commas aren't part of tags.
+                        ev.prevent_default();
+                        self.commit(tx_view);
+                    }
+                    "Backspace" if is_empty => {
+                        if let Some(tag) = self.tags.last().cloned() {
+                            self.remove(&tag, tx_view);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            TagInputIn::Input => {
+                let has_comma = self
+                    .o_input
+                    .as_ref()
+                    .map(|input| input.value().contains(','))
+                    .unwrap_or(false);
+                if has_comma {
+                    self.commit(tx_view);
+                }
+            }
+            TagInputIn::Commit => self.commit(tx_view),
+            TagInputIn::Remove(tag) => self.remove(tag, tx_view),
+            TagInputIn::Popular(tags) => {
+                self.popular = vec![];
+                add_tags(&mut self.popular, &tags.join(","));
+                self.suggest(tx_view);
+            }
+        }
+    }
+
+    fn view(
+        &self,
+        tx: &Transmitter<TagInputIn>,
+        rx: &Receiver<TagInputOut>,
+    ) -> ViewBuilder<HtmlElement> {
+        let chips = self
+            .tags
+            .iter()
+            .map(|tag| self.chip_builder(tag))
+            .collect::<Vec<_>>();
+        builder! {
+            <div class="tag-input">
+                <input
+                    cast:type = HtmlInputElement
+                    post:build = tx.contra_map(|input: &HtmlInputElement| {
+                        TagInputIn::PostBuild(input.clone())
+                    })
+                    on:keydown = tx.contra_filter_map(|ev: &Event| {
+                        ev.dyn_ref::<KeyboardEvent>().cloned().map(TagInputIn::KeyDown)
+                    })
+                    on:input = tx.contra_map(|_| TagInputIn::Input)
+                    on:blur = tx.contra_map(|_| TagInputIn::Commit)
+                    class="form-control"
+                    type="text"
+                    placeholder="Enter tags"
+                    list=&self.suggestions_id />
+                <datalist
+                    id=&self.suggestions_id
+                    patch:children=rx.branch_filter_map(|msg| msg.suggestions())>
+                </datalist>
+                <div class="tag-list" patch:children=rx.branch_filter_map(|msg| msg.chips())>
+                    {chips}
+                </div>
+            </div>
+        }
+    }
+}
+
+#[cfg(test)]
+mod widgets_tests {
+    use super::*;
+
+    #[test]
+    fn optional_values_may_be_blank() {
+        for validator in &[
+            Validator::Email,
+            Validator::MinLength(8),
+            Validator::MaxLength(2),
+            Validator::Url,
+        ] {
+            assert_eq!(validator.check(""), None);
+        }
+        assert_eq!(
+            Validator::Required.check("  "),
+            Some("can't be blank".to_string())
+        );
+    }
+
+    #[test]
+    fn checks_emails() {
+        for email in &["jake@jake.jake", "a.b+c@example.co.uk"] {
+            assert_eq!(Validator::Email.check(email), None, "{}", email);
+        }
+        for email in &[
+            "jake",
+            "@jake.jake",
+            "jake@",
+            "jake@jake",
+            "ja ke@jake.jake",
+            "a@b@c.d",
+        ] {
+            assert_eq!(
+                Validator::Email.check(email),
+                Some("is invalid".to_string()),
+                "{}",
+                email
+            );
+        }
+    }
+
+    #[test]
+    fn checks_urls() {
+        for url in &["https://example.com", "http://example.com/me.jpg?size=2"] {
+            assert_eq!(Validator::Url.check(url), None, "{}", url);
+        }
+        for url in &[
+            "example.com/me.jpg",
+            "https://",
+            "ftp://example.com",
+            "https://a b",
+        ] {
+            assert_eq!(
+                Validator::Url.check(url),
+                Some("is invalid".to_string()),
+                "{}",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn checks_lengths_in_characters() {
+        assert_eq!(Validator::MinLength(3).check("äöü"), None);
+        assert_eq!(
+            Validator::MinLength(4).check("äöü"),
+            Some("is too short (minimum is 4 characters)".to_string())
+        );
+        assert_eq!(
+            Validator::MaxLength(2).check("äöü"),
+            Some("is too long (maximum is 2 characters)".to_string())
+        );
+    }
+
+    #[test]
+    fn checks_matching_values() {
+        let password = Rc::new(RefCell::new("hunter22".to_string()));
+        let validator = Validator::Matches {
+            name: "password".to_string(),
+            value: password.clone(),
+        };
+        assert_eq!(validator.check("hunter22"), None);
+        *password.borrow_mut() = "hunter23".to_string();
+        assert_eq!(
+            validator.check("hunter22"),
+            Some("doesn't match password".to_string())
+        );
+    }
+
+    #[test]
+    fn adds_normalized_tags_once() {
+        let mut tags = vec!["rust".to_string()];
+        assert_eq!(
+            add_tags(&mut tags, " WebAssembly, rust,,Rust , mogwai"),
+            vec!["webassembly".to_string(), "mogwai".to_string()]
+        );
+        assert_eq!(tags, vec!["rust", "webassembly", "mogwai"]);
+        assert!(add_tags(&mut tags, " , ").is_empty());
+    }
+
+    #[test]
+    fn names_the_value_in_errors() {
+        let input: TextInput<HtmlInputElement> = TextInput::new("", "Email")
+            .with_validators("email", vec![Validator::Required, Validator::Email]);
+        assert_eq!(input.error(), Some("email can't be blank".to_string()));
+        let mut validity = FormValidity::default();
+        validity.check(&input);
+        assert!(!validity.is_valid());
+        validity.set("email", true);
+        assert!(validity.is_valid());
+    }
+}